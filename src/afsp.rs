@@ -0,0 +1,105 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+
+use crate::mfs::check::{self, Report};
+use crate::mfs::{
+    Geometry, PageKind, CHUNK_SIZE, DATA_PAGE_CHUNKS, MFS, PAGE_SIZE, VOLUME_MAGIC, VOLUME_VERSION,
+};
+
+// AFSP shows up on SPS (server) images. Its pages carry the same
+// 8778 55aa signature as MFS, so we read it with the MFS code and note
+// wherever its structure deviates from what an MFS would look like.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AFSP {
+    pub volume: MFS,
+    /// Structural differences to MFS: volume header, page and chunk
+    /// geometry, FAT layout
+    pub differences: Vec<String>,
+    /// Corruption as found by the MFS checker
+    pub check: Report,
+}
+
+impl Display for AFSP {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let o = self.volume.offset;
+        let p = self.volume.pages.len();
+        let h = self.volume.header;
+        let n = self.volume.files.len();
+        let d = self.differences.len();
+        write!(
+            f,
+            "AFSP @ {o:08x}, {p} pages, {h}, {n} files, {d} differences to MFS"
+        )
+    }
+}
+
+fn differences(data: &[u8], volume: &MFS) -> Result<Vec<String>, String> {
+    let mut res = Vec::<String>::new();
+    if !data.len().is_multiple_of(PAGE_SIZE) {
+        let s = data.len();
+        res.push(format!("size 0x{s:08x} is not a multiple of the page size"));
+    }
+
+    let h = volume.header;
+    let m = h.magic;
+    if m != VOLUME_MAGIC {
+        res.push(format!(
+            "volume magic {m:08x} instead of {VOLUME_MAGIC:08x}"
+        ));
+    }
+    let v = h.version;
+    if v != VOLUME_VERSION {
+        res.push(format!("volume version {v} instead of {VOLUME_VERSION}"));
+    }
+
+    let geo = Geometry::new(data.len())?;
+    let count = |k: PageKind| volume.pages.iter().filter(|p| p.kind == k).count();
+    let (sys, dat) = (count(PageKind::System), count(PageKind::Data));
+    if sys != geo.sys_pages {
+        let e = geo.sys_pages;
+        res.push(format!("{sys} system pages instead of {e}"));
+    }
+    if dat != geo.data_pages {
+        let e = geo.data_pages;
+        res.push(format!("{dat} data pages instead of {e}"));
+    }
+    // data chunk numbering continues after the system chunks, a page apart
+    let base = geo.data_base() as u16;
+    for p in volume.pages.iter().filter(|p| p.kind == PageKind::Data) {
+        let fc = p.header.first_chunk;
+        let aligned = fc
+            .checked_sub(base)
+            .is_some_and(|c| (c as usize).is_multiple_of(DATA_PAGE_CHUNKS));
+        if !aligned {
+            let i = p.index;
+            res.push(format!(
+                "page {i}: first chunk {fc:04x} not a multiple of {DATA_PAGE_CHUNKS} after {base:04x}"
+            ));
+        }
+    }
+
+    let c = h.capacity as usize;
+    let e = geo.data_chunks() * CHUNK_SIZE;
+    if c != e {
+        res.push(format!("capacity 0x{c:08x} instead of 0x{e:08x}"));
+    }
+    let l = volume.fat.len();
+    let e = h.files as usize + geo.data_chunks();
+    if l != e {
+        res.push(format!("{l} FAT entries instead of {e}"));
+    }
+    Ok(res)
+}
+
+impl AFSP {
+    pub fn new(data: &[u8], offset: usize) -> Result<Self, String> {
+        let volume = MFS::read(data, offset)?;
+        let differences = differences(data, &volume)?;
+        let check = check::check(data, offset);
+        Ok(Self {
+            volume,
+            differences,
+            check,
+        })
+    }
+}
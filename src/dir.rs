@@ -1,3 +1,7 @@
+pub mod gen1;
 pub mod gen2;
 pub mod gen3;
+pub mod hash;
+pub mod huffman;
+pub mod lzma;
 pub mod man;
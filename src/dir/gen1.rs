@@ -0,0 +1,148 @@
+use crate::dir::hash;
+use crate::dir::man::{Manifest, MANIFEST_MAGIC};
+use crate::ver::Version;
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::str::from_utf8;
+use zerocopy::FromBytes;
+use zerocopy_derive::{FromBytes, IntoBytes};
+
+// ME 2-5 (ICH8-ICH10) code partitions start with a `$MAN` manifest, which
+// shares its header with `$MN2`, followed by the partition name and the
+// module headers.
+const MODULE_MAGICS: [[u8; 4]; 2] = [*b"$MME", *b"$MDL"];
+
+// https://github.com/skochinsky/me-tools me_unpack.py MeModuleHeader1
+#[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ModuleHeader {
+    pub magic: [u8; 4],
+    pub guid: [u8; 0x10],
+    pub version: Version,
+    pub name: [u8; 0x10],
+    pub hash: [u8; 0x14], // SHA-1
+    pub size: u32,
+    pub flags: u32,
+    pub _48: u32,
+    pub _4c: u32,
+}
+
+impl ModuleHeader {
+    pub fn name(&self) -> String {
+        match from_utf8(&self.name) {
+            Ok(n) => n.trim_end_matches('\0').to_string(),
+            Err(_) => format!("{:02x?}", self.name),
+        }
+    }
+}
+
+/// A module header. NOTE: Where the module data is stored is not known;
+/// the headers have no offset field that we could confirm.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Entry {
+    pub header: ModuleHeader,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        self.header.name()
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.name();
+        let v = self.header.version;
+        let s = self.header.size;
+        let fl = self.header.flags;
+        write!(f, "{n:16} {s:08x}, flags {fl:08x}, version {v}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Directory {
+    pub manifest: Manifest,
+    /// Whether the manifest signature is valid
+    pub signature: Result<bool, String>,
+    pub entries: Vec<Entry>,
+    pub offset: usize,
+    pub name: String,
+}
+
+impl Display for Directory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = &self.name;
+        let o = self.offset;
+        let m = &self.manifest;
+        write!(f, "{n} @ {o:08x}, {m}")
+    }
+}
+
+const NAME_SIZE: usize = 12;
+const MODULE_HEADER_SIZE: usize = core::mem::size_of::<ModuleHeader>();
+
+impl Directory {
+    /// Get the contents of a module. This fails as long as the location of
+    /// the module data is not known, rather than reading from a guess.
+    pub fn module(&self, _image: &[u8], e: &Entry) -> Result<Vec<u8>, String> {
+        Err(format!("module {} location unknown", e.name()))
+    }
+
+    /// Check each module, decompressed, against the SHA-1 hash in its
+    /// header. The result per module is whether the hash matches, or why it could not be checked.
+    pub fn verify_hashes(&self, image: &[u8]) -> Vec<(String, Result<bool, String>)> {
+        self.entries
+            .iter()
+            .map(|e| (e.name(), self.verify_hash(image, e)))
+            .collect()
+    }
+
+    fn verify_hash(&self, image: &[u8], e: &Entry) -> Result<bool, String> {
+        let h = &e.header.hash;
+        if h.iter().all(|b| *b == 0) {
+            return Err("no hash".to_string());
+        }
+        hash::matches(&self.module(image, e)?, h)
+    }
+
+    pub fn new(data: &[u8], offset: usize) -> Result<Self, String> {
+        let Ok(manifest) = Manifest::new(data) else {
+            return Err("cannot parse Gen 1 directory manifest".to_string());
+        };
+        if manifest.header.magic != *MANIFEST_MAGIC {
+            return Err("not a Gen 1 directory manifest".to_string());
+        }
+        let count = manifest.header.entries as usize;
+        let man_size = manifest.header.length();
+        let Some(n) = data.get(man_size..man_size + NAME_SIZE) else {
+            return Err("cannot parse ME FW Gen 1 partition name".to_string());
+        };
+        let name = match from_utf8(n) {
+            Ok(n) => n.trim_end_matches('\0').to_string(),
+            Err(_) => format!("{n:02x?}"),
+        };
+        let pos = man_size + NAME_SIZE;
+        let mut entries = Vec::<Entry>::with_capacity(count);
+        for i in 0..count {
+            let p = pos + i * MODULE_HEADER_SIZE;
+            let Some(Ok((header, _))) = data.get(p..).map(ModuleHeader::read_from_prefix) else {
+                return Err(format!("cannot parse ME FW Gen 1 module header @ {p:08x}"));
+            };
+            if !MODULE_MAGICS.contains(&header.magic) {
+                return Err(format!(
+                    "ME FW Gen 1 module magic not found, got: {:02x?}",
+                    header.magic
+                ));
+            }
+            entries.push(Entry { header });
+        }
+        let signature = manifest.verify(data);
+        Ok(Self {
+            manifest,
+            signature,
+            entries,
+            offset,
+            name,
+        })
+    }
+}
@@ -1,13 +1,19 @@
-use crate::dir::man::{self, Manifest};
+use crate::dir::huffman::Dictionaries;
+use crate::dir::man::{Manifest, MANIFEST2_MAGIC};
+use crate::dir::{hash, lzma};
+use crate::elf::{self, Segment, PF_R, PF_W, PF_X};
 use core::fmt::{self, Display};
 use serde::{Deserialize, Serialize};
 use std::str::from_utf8;
 use zerocopy::{FromBytes, Ref};
 use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
 
+pub mod trailer;
+use trailer::{Sku, Trailer};
+
 const ENTRY_MAGIC: &[u8] = b"$MME";
-const SIG_LUT: u32 = u32::from_le_bytes(*b"LLUT");
-const SIG_LZMA: u32 = u32::from_le_bytes([0x36, 0x00, 0x40, 0x00]);
+pub const SIG_LUT: u32 = u32::from_le_bytes(*b"LLUT");
+pub const SIG_LZMA: u32 = u32::from_le_bytes(lzma::SIG);
 
 // https://github.com/skochinsky/me-tools me_unpack.py MeModuleHeader2
 #[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
@@ -29,7 +35,7 @@ pub struct Entry {
     pub _5c: u32,          // so far all 0
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum Compression {
     Uncompressed,
     Huffman,
@@ -37,6 +43,116 @@ pub enum Compression {
     Unknown,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PowerType {
+    Reserved,
+    M0Only,
+    M3Only,
+    Live,
+}
+
+// https://github.com/skochinsky/me-tools me_unpack.py MeModuleHeader2
+/// The flags of an entry; fields not understood yet are kept as unknown.
+/// NOTE: Neither me_unpack nor MEAnalyzer decode a module type from the
+/// flags; if there is one, it is in the unknown bits.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ModuleFlags {
+    pub unknown0: bool,
+    pub power_type: PowerType, // bits 1-2
+    pub unknown3: bool,
+    pub compression: Compression, // bits 4-6
+    pub unknown7: bool,
+    pub privileged: bool,
+    pub unknown9: u8,   // bits 9-16
+    pub rapi: u8,       // bits 17-19
+    pub kapi: u8,       // bits 20-21
+    pub unknown22: u16, // bits 22-31
+}
+
+impl From<u32> for ModuleFlags {
+    fn from(f: u32) -> Self {
+        let bit = |n: u32| (f >> n) & 1 != 0;
+        let power_type = match (f >> 1) & 0b11 {
+            0 => PowerType::Reserved,
+            1 => PowerType::M0Only,
+            2 => PowerType::M3Only,
+            _ => PowerType::Live,
+        };
+        let compression = match (f >> 4) & 0b111 {
+            0 => Compression::Uncompressed,
+            1 => Compression::Huffman,
+            2 => Compression::Lzma,
+            _ => Compression::Unknown,
+        };
+        Self {
+            unknown0: bit(0),
+            power_type,
+            unknown3: bit(3),
+            compression,
+            unknown7: bit(7),
+            privileged: bit(8),
+            unknown9: (f >> 9) as u8,
+            rapi: ((f >> 17) & 0b111) as u8,
+            kapi: ((f >> 20) & 0b11) as u8,
+            unknown22: (f >> 22) as u16,
+        }
+    }
+}
+
+impl Display for ModuleFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = self.power_type;
+        let c = self.compression;
+        let pr = if self.privileged {
+            "privileged"
+        } else {
+            "unprivileged"
+        };
+        let r = self.rapi;
+        let k = self.kapi;
+        let u0 = self.unknown0 as u8;
+        let u3 = self.unknown3 as u8;
+        let u7 = self.unknown7 as u8;
+        let u9 = self.unknown9;
+        let u22 = self.unknown22;
+        write!(
+            f,
+            "power {p:?}, {c:?}, {pr}, RAPI {r} KAPI {k}, unknown {u0} {u3} {u7} {u9:02x} {u22:03x}"
+        )
+    }
+}
+
+/// When a module is loaded, from its pre-UMA and post-UMA parts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LoadType {
+    PreUma,
+    PostUma,
+    Split,
+}
+
+/// How an entry is loaded, with the words following the flags, whose
+/// meaning is unknown.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LoadAttributes {
+    pub load_type: LoadType,
+    pub pre_uma_size: u32,
+    pub post_uma_size: u32,
+    pub unknown: [u32; 3], // `_54`, `_58`, `_5c`
+}
+
+impl Display for LoadAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let l = self.load_type;
+        let pre = self.pre_uma_size;
+        let post = self.post_uma_size;
+        let u = self.unknown;
+        write!(
+            f,
+            "load {l:?}, pre-UMA {pre:08x}, post-UMA {post:08x}, unknown {u:08x?}"
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct BinaryMap {
     pub rapi: u32, // 3 bits, really
@@ -47,21 +163,47 @@ pub struct BinaryMap {
 }
 
 impl Entry {
+    pub fn name(&self) -> String {
+        match from_utf8(&self.name) {
+            Ok(n) => n.trim_end_matches('\0').to_string(),
+            Err(_) => format!("{:02x?}", self.name),
+        }
+    }
+
+    pub fn module_flags(&self) -> ModuleFlags {
+        ModuleFlags::from(self.flags)
+    }
+
     pub fn compression_type(&self) -> Compression {
-        let comp_flag = (self.flags >> 4) & 0b111;
-        match comp_flag {
-            0 => Compression::Uncompressed,
-            1 => Compression::Huffman,
-            2 => Compression::Lzma,
-            _ => Compression::Unknown,
+        self.module_flags().compression
+    }
+
+    /// Size of the part loaded after UMA is available
+    pub fn post_uma_size(&self) -> u32 {
+        self.memory_size.saturating_sub(self.pre_uma_size)
+    }
+
+    pub fn load_attributes(&self) -> LoadAttributes {
+        let pre_uma_size = self.pre_uma_size;
+        let post_uma_size = self.post_uma_size();
+        let load_type = match (pre_uma_size, post_uma_size) {
+            (_, 0) => LoadType::PreUma,
+            (0, _) => LoadType::PostUma,
+            _ => LoadType::Split,
+        };
+        LoadAttributes {
+            load_type,
+            pre_uma_size,
+            post_uma_size,
+            unknown: [self._54, self._58, self._5c],
         }
     }
 
     pub fn bin_map(&self) -> BinaryMap {
         let b = self.mod_base;
-        let f = self.flags;
-        let rapi = (f >> 17) & 0b111;
-        let kapi = (f >> 20) & 0b11;
+        let f = self.module_flags();
+        let rapi = f.rapi as u32;
+        let kapi = f.kapi as u32;
         let code_start = (b + (rapi + kapi) * 0x1000) as usize;
         let code_end = (b + self.code_size) as usize;
         let data_end = (b + self.memory_size) as usize;
@@ -91,10 +233,7 @@ impl Display for BinaryMap {
 
 impl Display for Entry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let n = match from_utf8(&self.name) {
-            Ok(n) => n.trim_end_matches('\0').to_string(),
-            Err(_) => format!("{:02x?}", self.name),
-        };
+        let n = self.name();
         let o = self.offset;
         let s = self.size;
         let e = self.entry_point;
@@ -102,6 +241,120 @@ impl Display for Entry {
     }
 }
 
+// https://github.com/skochinsky/me-tools me_unpack.py HuffmanLUTHeader
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct LutHeader {
+    pub magic: [u8; 4],
+    pub chunks: u32,
+    pub addr_base: u32, // memory address of the first chunk
+    pub span_size: u32,
+    pub huff_length: u32,
+    pub huff_start: u32, // relative to the partition, like the chunk offsets
+    pub flags: u32,
+    pub _1c: [u32; 5],
+    pub page_size: u32, // size of a chunk once decompressed
+    pub version: [u16; 2],
+    pub chipset: [u8; 4],
+    pub revision: [u8; 4],
+}
+
+const LUT_HEADER_SIZE: usize = core::mem::size_of::<LutHeader>();
+const LUT_OFFSET_MASK: u32 = 0x00ff_ffff;
+const DEFAULT_PAGE_SIZE: usize = 0x1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Lut {
+    pub header: LutHeader,
+    pub chunks: Vec<u32>, // offset in the lower 24 bits, flags in the upper 8
+}
+
+impl Lut {
+    pub fn new(data: &[u8]) -> Result<Self, String> {
+        let Ok((header, _)) = LutHeader::read_from_prefix(data) else {
+            return Err("cannot parse LLUT header".to_string());
+        };
+        if u32::from_le_bytes(header.magic) != SIG_LUT {
+            return Err(format!("LLUT magic not found, got: {:02x?}", header.magic));
+        }
+        let count = header.chunks as usize;
+        let slice = &data[LUT_HEADER_SIZE..];
+        let Ok((r, _)) = Ref::<_, [u32]>::from_prefix_with_elems(slice, count) else {
+            return Err(format!("cannot parse {count} LLUT chunks"));
+        };
+        let chunks = r.to_vec();
+        Ok(Self { header, chunks })
+    }
+
+    pub fn chipset(&self) -> String {
+        match from_utf8(&self.header.chipset) {
+            Ok(n) => n.trim_end_matches(['\0', ' ']).to_string(),
+            Err(_) => format!("{:02x?}", self.header.chipset),
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        match self.header.page_size as usize {
+            0 => DEFAULT_PAGE_SIZE,
+            s => s,
+        }
+    }
+
+    /// Decompress the pages covering the given memory range.
+    /// `part` is the partition that the chunk offsets refer to.
+    pub fn decompress(
+        &self,
+        part: &[u8],
+        addr: u32,
+        size: usize,
+        dicts: &Dictionaries,
+    ) -> Result<Vec<u8>, String> {
+        let ps = self.page_size();
+        let base = self.header.addr_base;
+        if addr < base {
+            return Err(format!("address {addr:08x} below LLUT base {base:08x}"));
+        }
+        let first = (addr - base) as usize / ps;
+        let count = size.div_ceil(ps);
+        if first + count > self.chunks.len() {
+            return Err(format!("address range {addr:08x}+{size:x} beyond LLUT"));
+        }
+        let chipset = self.chipset();
+        // A chunk extends up to the next one in flash.
+        let h = self.header;
+        let huff_end = h.huff_start.saturating_add(h.huff_length) as usize;
+        let mut offsets: Vec<usize> = self
+            .chunks
+            .iter()
+            .map(|c| (c & LUT_OFFSET_MASK) as usize)
+            .collect();
+        offsets.sort();
+
+        let mut out = Vec::<u8>::with_capacity(count * ps);
+        for c in &self.chunks[first..first + count] {
+            let o = (c & LUT_OFFSET_MASK) as usize;
+            let flags = c >> 24;
+            // unpopulated pages, e.g., BSS
+            if *c == 0 {
+                out.resize(out.len() + ps, 0);
+                continue;
+            }
+            let end = match offsets.iter().find(|x| **x > o) {
+                Some(e) => *e,
+                None => huff_end.min(part.len()),
+            };
+            let Some(d) = part.get(o..end) else {
+                return Err(format!("Huffman chunk @ {o:08x} out of bounds"));
+            };
+            // NOTE: bit 6 of the flags appears to select the data dictionary.
+            let dict = dicts.get(&chipset, ((flags >> 6) & 1) as usize)?;
+            out.extend(dict.decode(d, ps)?);
+        }
+        out.truncate(size);
+        Ok(out)
+    }
+}
+
 #[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
 #[repr(C, packed)]
 pub struct Header {
@@ -113,8 +366,15 @@ pub struct Header {
 #[repr(C)]
 pub struct Directory {
     pub manifest: Manifest,
+    /// Whether the manifest signature is valid
+    pub signature: Result<bool, String>,
     pub header: Header,
     pub entries: Vec<Entry>,
+    /// Structures following the entries, e.g., $SKU
+    pub trailers: Vec<Trailer>,
+    /// Offset of the Huffman LLUT in the partition, which all Huffman
+    /// compressed modules point to, or why the entries disagree on it
+    pub lut_offset: Result<Option<usize>, String>,
     pub offset: usize,
     pub name: String,
 }
@@ -123,24 +383,105 @@ impl Display for Directory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let n = &self.name;
         let o = self.offset;
-        let m = self.manifest;
+        let m = &self.manifest;
         write!(f, "{n} @ {o:08x}, {m}")
     }
 }
 
 const HEADER_SIZE: usize = core::mem::size_of::<Header>();
+const ENTRY_SIZE: usize = core::mem::size_of::<Entry>();
 
 impl Directory {
+    /// Get the contents of a module, decompressed to its `memory_size`
+    /// image. `image` is the whole flash image that `offset` refers to.
+    pub fn module(&self, image: &[u8], e: &Entry, dicts: &Dictionaries) -> Result<Vec<u8>, String> {
+        let part = &image[self.offset..];
+        let o = e.offset as usize;
+        let s = e.size as usize;
+        let n = e.name();
+        match e.compression_type() {
+            Compression::Uncompressed => match part.get(o..o + s) {
+                Some(d) => Ok(d.to_vec()),
+                None => Err(format!("module {n} out of bounds")),
+            },
+            Compression::Huffman => {
+                let Some(d) = part.get(o..) else {
+                    return Err(format!("module {n} out of bounds"));
+                };
+                let lut = Lut::new(d)?;
+                let size = e.memory_size as usize;
+                lut.decompress(part, e.mod_base, size, dicts)
+            }
+            Compression::Lzma => {
+                let Some(d) = part.get(o..o + s) else {
+                    return Err(format!("module {n} out of bounds"));
+                };
+                lzma::decompress(d, Some(e.memory_size as usize))
+            }
+            c => Err(format!("module {n}: {c:?} compression not supported")),
+        }
+    }
+
+    /// Get a module as an ELF file, with the API pages, code and data mapped
+    /// to their runtime addresses as per `Entry::bin_map`.
+    pub fn elf(&self, image: &[u8], e: &Entry, dicts: &Dictionaries) -> Result<Vec<u8>, String> {
+        let m = self.module(image, e, dicts)?;
+        let b = e.bin_map();
+        let base = e.mod_base;
+        let (cs, ce, de) = (b.code_start as u32, b.code_end as u32, b.data_end as u32);
+        let segments: Vec<Segment> = [
+            Segment::from_image("api", &m, base, base, cs, PF_R),
+            Segment::from_image(".text", &m, base, cs, ce, PF_R | PF_X),
+            Segment::from_image(".data", &m, base, ce, de, PF_R | PF_W),
+        ]
+        .into_iter()
+        .filter(|s| s.mem_size > 0)
+        .collect();
+        Ok(elf::write(elf::EM_ARC_COMPACT, e.entry_point, &segments))
+    }
+
+    pub fn sku(&self) -> Option<&Sku> {
+        self.trailers.iter().find_map(|t| match t {
+            Trailer::Sku(s, _) => Some(s),
+            _ => None,
+        })
+    }
+
+    /// Check each module, decompressed to its `memory_size` image, against
+    /// the hash in its entry. The result per module is whether the hash
+    /// matches, or why it could not be checked.
+    pub fn verify_hashes(
+        &self,
+        image: &[u8],
+        dicts: &Dictionaries,
+    ) -> Vec<(String, Result<bool, String>)> {
+        self.entries
+            .iter()
+            .map(|e| (e.name(), self.verify_hash(image, e, dicts)))
+            .collect()
+    }
+
+    fn verify_hash(&self, image: &[u8], e: &Entry, dicts: &Dictionaries) -> Result<bool, String> {
+        if e.hash.iter().all(|b| *b == 0) {
+            return Err("no hash".to_string());
+        }
+        hash::matches(&self.module(image, e, dicts)?, &e.hash)
+    }
+
     pub fn new(data: &[u8], offset: usize) -> Result<Self, String> {
         let Ok(manifest) = Manifest::new(data) else {
             return Err("cannot parse Gen 2 directory manifest".to_string());
         };
+        if manifest.header.magic != *MANIFEST2_MAGIC {
+            return Err("not a Gen 2 directory manifest".to_string());
+        }
         let count = manifest.header.entries as usize;
-        let d = &data[man::MANIFEST_SIZE..];
+        let man_size = manifest.header.length();
+        let d = &data[man_size..];
         let Ok((header, _)) = Header::read_from_prefix(d) else {
             return Err("cannot parse ME FW Gen 2 directory header".to_string());
         };
-        let pos = man::MANIFEST_SIZE + HEADER_SIZE;
+        let pos = man_size + HEADER_SIZE;
         let slice = &data[pos..];
         let Ok((r, _)) = Ref::<_, [Entry]>::from_prefix_with_elems(slice, count) else {
             return Err(format!(
@@ -149,14 +490,40 @@ impl Directory {
             ));
         };
         let entries = r.to_vec();
+        if let Some(e) = entries.iter().find(|e| e.magic != *ENTRY_MAGIC) {
+            return Err(format!(
+                "ME FW Gen 2 entry magic not found, got: {:02x?}",
+                e.magic
+            ));
+        }
         let name = match from_utf8(&header.name) {
             Ok(n) => n.trim_end_matches('\0').to_string(),
             Err(_) => format!("{:02x?}", header.name),
         };
+        let trailers = match data.get(pos + count * ENTRY_SIZE..) {
+            Some(d) => Trailer::parse_all(d),
+            None => Vec::new(),
+        };
+        // see https://github.com/skochinsky/me-tools me_unpack.py huff_start
+        let mut huffman = entries
+            .iter()
+            .filter(|e| e.compression_type() == Compression::Huffman)
+            .map(|e| e.offset as usize);
+        let first = huffman.next();
+        let lut_offset = match (first, huffman.find(|o| Some(*o) != first)) {
+            (Some(f), Some(o)) => Err(format!(
+                "Huffman modules disagree on the LLUT offset: {f:08x} vs {o:08x}"
+            )),
+            _ => Ok(first),
+        };
+        let signature = manifest.verify(data);
         Ok(Self {
             manifest,
+            signature,
             header,
             entries,
+            trailers,
+            lut_offset,
             offset,
             name,
         })
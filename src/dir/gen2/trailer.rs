@@ -0,0 +1,134 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::str::from_utf8;
+use zerocopy::FromBytes;
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
+
+// After the `$MME` entries, ME 6-10 code partitions carry more structures,
+// each starting with a tag and its length in dwords, header included.
+// see https://github.com/skochinsky/me-tools me_unpack.py and
+// https://github.com/platomav/MEAnalyzer ($SKU)
+pub const UDC: [u8; 4] = *b"$UDC";
+pub const GLT: [u8; 4] = *b"$GLT";
+pub const SKU: [u8; 4] = *b"$SKU";
+pub const MCP: [u8; 4] = *b"$MCP";
+
+const KNOWN: [[u8; 4]; 4] = [UDC, GLT, SKU, MCP];
+// sanity limit, the structures seen so far are tiny
+const MAX_LENGTH: usize = 0x1000;
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct TagHeader {
+    pub tag: [u8; 4],
+    pub length: u32, // in dwords
+}
+
+const TAG_HEADER_SIZE: usize = core::mem::size_of::<TagHeader>();
+
+/// Update code, e.g., for the PAVP module
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Udc {
+    pub subtag: [u8; 4],
+    pub hash: [u8; 32],
+    pub name: [u8; 16],
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl Udc {
+    pub fn name(&self) -> String {
+        match from_utf8(&self.name) {
+            Ok(n) => n.trim_end_matches('\0').to_string(),
+            Err(_) => format!("{:02x?}", self.name),
+        }
+    }
+}
+
+/// SKU attributes; MEAnalyzer maps the value to a SKU name per firmware
+/// version, e.g., 1.5MB vs 5MB firmware.
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Sku {
+    pub attributes: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Trailer {
+    Udc(Udc),
+    /// Huffman global table; NOTE: the meaning of the words is not known.
+    /// The LLUT offset comes from the Huffman module entries instead.
+    Glt(Vec<u32>),
+    /// SKU attributes and any further words, whose meaning is not known
+    Sku(Sku, Vec<u32>),
+    Mcp(Vec<u32>),
+    /// A tag that we do not know, or a known one that we cannot parse,
+    /// e.g., because of an unexpected length
+    Unknown([u8; 4], Vec<u8>),
+}
+
+fn words(d: &[u8]) -> Vec<u32> {
+    d.chunks_exact(4)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        .collect()
+}
+
+impl Trailer {
+    fn new(tag: [u8; 4], d: &[u8]) -> Self {
+        match tag {
+            UDC => match Udc::read_from_bytes(d) {
+                Ok(u) => Self::Udc(u),
+                Err(_) => Self::Unknown(tag, d.to_vec()),
+            },
+            GLT => Self::Glt(words(d)),
+            SKU => match Sku::read_from_prefix(d) {
+                Ok((s, rest)) => Self::Sku(s, words(rest)),
+                Err(_) => Self::Unknown(tag, d.to_vec()),
+            },
+            MCP => Self::Mcp(words(d)),
+            _ => Self::Unknown(tag, d.to_vec()),
+        }
+    }
+
+    /// Parse the structures at the start of `data`, until an unknown tag.
+    pub fn parse_all(data: &[u8]) -> Vec<Self> {
+        let mut res = Vec::<Self>::new();
+        let mut d = data;
+        while let Ok((h, _)) = TagHeader::read_from_prefix(d) {
+            let l = h.length as usize * 4;
+            let valid = (TAG_HEADER_SIZE..=MAX_LENGTH.min(d.len())).contains(&l);
+            if !KNOWN.contains(&h.tag) || !valid {
+                break;
+            }
+            res.push(Self::new(h.tag, &d[TAG_HEADER_SIZE..l]));
+            d = &d[l..];
+        }
+        res
+    }
+}
+
+impl Display for Trailer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Udc(u) => {
+                let t = String::from_utf8_lossy(&u.subtag);
+                let n = u.name();
+                let o = u.offset;
+                let s = u.size;
+                write!(f, "$UDC {t} {n} {s:08x} @ {o:08x}")
+            }
+            Self::Glt(w) => write!(f, "$GLT {w:08x?}"),
+            Self::Sku(s, w) => {
+                let a = s.attributes;
+                write!(f, "$SKU attributes {a:08x}, unknown {w:08x?}")
+            }
+            Self::Mcp(w) => write!(f, "$MCP {w:08x?}"),
+            Self::Unknown(t, d) => {
+                let t = String::from_utf8_lossy(t);
+                let l = d.len();
+                write!(f, "{t} cannot parse 0x{l:x} bytes")
+            }
+        }
+    }
+}
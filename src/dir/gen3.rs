@@ -1,4 +1,10 @@
-use crate::dir::man::Manifest;
+use crate::dir::huffman::Dictionaries;
+use crate::dir::man::{
+    ext::{self, Extension, ModuleAttributes, ProcessAttributes},
+    Manifest,
+};
+use crate::dir::{hash, lzma};
+use crate::elf::{self, Segment, PF_R, PF_W, PF_X};
 use core::fmt::{self, Display};
 use serde::{Deserialize, Serialize};
 use zerocopy::FromBytes;
@@ -9,16 +15,136 @@ pub const CPD_MAGIC: &str = "$CPD";
 // see https://troopers.de/downloads/troopers17/TR17_ME11_Static.pdf
 #[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
 #[repr(C, packed)]
-pub struct CPDHeader {
+pub struct CPDHeaderV1 {
     pub magic: [u8; 4],
     pub entries: u32,
-    pub version_or_checksum: u32,
+    pub header_version: u8, // 1
+    pub entry_version: u8,
+    pub header_length: u8,
+    pub checksum: u8,
     pub part_name: [u8; 4],
-    // Some ME variants have an extra 4 bytes here...
-    // _10: u32,
 }
 
-const HEADER_SIZE: usize = core::mem::size_of::<CPDHeader>();
+// see UEFITool common/me.h CPD_REV2_HEADER
+#[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct CPDHeaderV2 {
+    pub magic: [u8; 4],
+    pub entries: u32,
+    pub header_version: u8, // 2
+    pub entry_version: u8,
+    pub header_length: u8,
+    pub _reserved: u8,
+    pub part_name: [u8; 4],
+    pub crc32: u32,
+}
+
+const V1_CHECKSUM_OFFSET: usize = 11;
+const V2_CHECKSUM_OFFSET: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum CPDHeader {
+    V1(CPDHeaderV1),
+    V2(CPDHeaderV2),
+}
+
+impl CPDHeader {
+    pub fn new(data: &[u8]) -> Result<Self, String> {
+        let Ok((h, _)) = CPDHeaderV1::read_from_prefix(data) else {
+            return Err("could not parse CPD header".to_string());
+        };
+        match h.header_version {
+            1 => Ok(Self::V1(h)),
+            2 => match CPDHeaderV2::read_from_prefix(data) {
+                Ok((h, _)) => Ok(Self::V2(h)),
+                Err(_) => Err("could not parse CPD header v2".to_string()),
+            },
+            v => Err(format!("unknown CPD header version {v}")),
+        }
+    }
+
+    pub fn entries(&self) -> u32 {
+        match self {
+            Self::V1(h) => h.entries,
+            Self::V2(h) => h.entries,
+        }
+    }
+
+    pub fn part_name(&self) -> [u8; 4] {
+        match self {
+            Self::V1(h) => h.part_name,
+            Self::V2(h) => h.part_name,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::V1(_) => core::mem::size_of::<CPDHeaderV1>(),
+            Self::V2(_) => core::mem::size_of::<CPDHeaderV2>(),
+        }
+    }
+
+    pub fn checksum(&self) -> u32 {
+        match self {
+            Self::V1(h) => h.checksum as u32,
+            Self::V2(h) => h.crc32,
+        }
+    }
+
+    /// Compute the checksum over the header and entries in `data`, with
+    /// the checksum field itself zeroed: v1 uses an 8-bit sum that makes
+    /// all bytes add up to 0, v2 a CRC32.
+    pub fn compute_checksum(&self, data: &[u8]) -> Result<u32, String> {
+        let size = self.size() + self.entries() as usize * ENTRY_SIZE;
+        let Some(d) = data.get(..size) else {
+            return Err("CPD entries out of bounds".to_string());
+        };
+        let mut d = d.to_vec();
+        match self {
+            Self::V1(_) => {
+                d[V1_CHECKSUM_OFFSET] = 0;
+                let sum = d.iter().fold(0u8, |s, b| s.wrapping_add(*b));
+                Ok(sum.wrapping_neg() as u32)
+            }
+            Self::V2(_) => {
+                d[V2_CHECKSUM_OFFSET..V2_CHECKSUM_OFFSET + 4].fill(0);
+                Ok(crc32(&d))
+            }
+        }
+    }
+}
+
+impl Display for CPDHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hv, ev) = match self {
+            Self::V1(h) => (h.header_version, h.entry_version),
+            Self::V2(h) => (h.header_version, h.entry_version),
+        };
+        let e = self.entries();
+        let c = self.checksum();
+        let kind = match self {
+            Self::V1(_) => "checksum",
+            Self::V2(_) => "CRC32",
+        };
+        write!(
+            f,
+            "header v{hv}, entries v{ev}, {e} entries, {kind} {c:08x}"
+        )
+    }
+}
+
+// CRC-32 as in zlib, reflected with polynomial 0x04c11db7
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(0xffff_ffff, |c, &b| {
+        (0..8).fold(c ^ b as u32, |c, _| {
+            if c & 1 != 0 {
+                (c >> 1) ^ 0xedb8_8320
+            } else {
+                c >> 1
+            }
+        })
+    })
+}
 
 #[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
 #[repr(C)]
@@ -26,10 +152,63 @@ pub struct CPDEntry {
     pub name: [u8; 12],
     pub offset: u32,
     pub size: u32,
+    // UEFITool common/me.h CPD_ENTRY has this as Reserved. It is not used
+    // to tell the compression, which is in the `.met` module attributes.
     pub compression_flag: u32,
 }
 
+const ENTRY_SIZE: usize = core::mem::size_of::<CPDEntry>();
+
+const OFFSET_MASK: u32 = 0x01ff_ffff;
+const HUFFMAN_FLAG: u32 = 1 << 25;
+
+// see UEFITool common/me.h CPD_ENTRY
+/// The attributes packed into the offset field of an entry, and the
+/// following reserved word, which older tools call the compression flag.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Attributes {
+    pub offset: u32,    // 25 bits
+    pub huffman: bool,  // bit 25
+    pub reserved: u8,   // upper 6 bits, so far all 0
+    pub reserved2: u32, // `compression_flag`, unused
+}
+
+impl Display for Attributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = self.reserved;
+        let r2 = self.reserved2;
+        let h = if self.huffman { "H" } else { "-" };
+        write!(f, "{h} {r:02x} {r2:08x}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Uncompressed,
+    Huffman,
+    Lzma,
+}
+
 impl CPDEntry {
+    /// Offset of the file relative to the CPD, without the flags.
+    pub fn offset(&self) -> u32 {
+        self.offset & OFFSET_MASK
+    }
+
+    pub fn attributes(&self) -> Attributes {
+        let o = self.offset;
+        Attributes {
+            offset: o & OFFSET_MASK,
+            huffman: o & HUFFMAN_FLAG != 0,
+            reserved: (o >> 26) as u8,
+            reserved2: self.compression_flag,
+        }
+    }
+
+    pub fn is_huffman(&self) -> bool {
+        self.attributes().huffman
+    }
+
     pub fn name(&self) -> String {
         match std::str::from_utf8(&self.name) {
             Ok(n) => n.trim_end_matches('\0').to_string(),
@@ -41,12 +220,12 @@ impl CPDEntry {
 impl Display for CPDEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let n = self.name();
-        let o = self.offset;
+        let o = self.offset();
         let s = self.size;
         let end = o + s;
-        let flag = self.compression_flag;
+        let a = self.attributes();
 
-        write!(f, "{n:13} @ 0x{o:06x}:0x{end:06x} (0x{s:06x}) {flag:032b}")
+        write!(f, "{n:13} @ 0x{o:06x}:0x{end:06x} (0x{s:06x}) {a}")
     }
 }
 
@@ -54,53 +233,389 @@ impl Display for CPDEntry {
 #[repr(C)]
 pub struct CodePartitionDirectory {
     pub header: CPDHeader,
+    /// Ok if the checksum in the header matches, else what we computed
+    pub checksum: Result<(), String>,
     pub manifest: Result<Manifest, String>,
+    /// Whether the signature of `manifest` is valid
+    pub signature: Result<bool, String>,
+    pub manifests: Vec<CPDManifest>,
+    pub metadata: Vec<CPDMetadata>,
     pub entries: Vec<CPDEntry>,
     pub offset: usize,
     pub name: String,
 }
 
-// TODO: See https://github.com/skochinsky/me-tools class CPDEntry
-// What is the other u8?!
-const OFFSET_MASK: u32 = 0xffffff;
+const MANIFEST_EXTS: [&str; 2] = [".man", ".key"];
+
+/// A manifest (`.man`) or key manifest (`.key`) file in a CPD and the files
+/// that it covers.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CPDManifest {
+    pub name: String,
+    pub manifest: Result<Manifest, String>,
+    /// Whether the manifest signature is valid
+    pub signature: Result<bool, String>,
+    pub extensions: Result<Vec<Extension>, String>,
+    /// The modules listed in the partition info and signed package info
+    /// extensions, and their metadata. Key manifests sign keys instead,
+    /// which are listed in their key manifest extension.
+    pub files: Vec<String>,
+}
+
+impl CPDManifest {
+    /// Parse all manifests. `data` is the CPD that the entry offsets refer
+    /// to.
+    pub fn all(data: &[u8], entries: &[CPDEntry]) -> Vec<Self> {
+        let names: Vec<String> = entries.iter().map(|e| e.name()).collect();
+        entries
+            .iter()
+            .filter(|e| MANIFEST_EXTS.iter().any(|x| e.name().ends_with(x)))
+            .map(|e| {
+                let b = data.get(e.offset() as usize..).unwrap_or_default();
+                let manifest = Manifest::new(b);
+                let (signature, extensions): (_, Result<Vec<_>, _>) = match &manifest {
+                    Ok(m) => (m.verify(b), m.extensions(b).collect()),
+                    Err(e) => (Err(e.clone()), Err(e.clone())),
+                };
+                let files = match &extensions {
+                    Ok(exts) => Self::modules(exts)
+                        .flat_map(|m| [format!("{m}.met"), m])
+                        .filter(|n| names.contains(n))
+                        .collect(),
+                    Err(_) => Vec::new(),
+                };
+                Self {
+                    name: e.name(),
+                    manifest,
+                    signature,
+                    extensions,
+                    files,
+                }
+            })
+            .collect()
+    }
+
+    fn modules(exts: &[Extension]) -> impl Iterator<Item = String> + '_ {
+        exts.iter().flat_map(|x| match x {
+            Extension::PartitionInfo(_, _, m) => m.iter().map(|m| m.entry.name()).collect(),
+            Extension::SignedPackageInfo(_, m) => m.iter().map(|m| m.entry.name()).collect(),
+            _ => Vec::new(),
+        })
+    }
+}
+
+/// A metadata (`.met`) file in a CPD, which describes the module of the
+/// same name.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CPDMetadata {
+    pub name: String,
+    pub extensions: Result<Vec<Extension>, String>,
+}
+
+impl CPDMetadata {
+    /// Parse all metadata files. `hash_size` is that of the manifest.
+    pub fn all(data: &[u8], entries: &[CPDEntry], hash_size: usize) -> Vec<Self> {
+        entries
+            .iter()
+            .filter(|e| e.name().ends_with(".met"))
+            .map(|e| {
+                let o = e.offset() as usize;
+                let extensions = match data.get(o..o + e.size as usize) {
+                    Some(d) => ext::parse_all(d, hash_size),
+                    None => Err("metadata out of bounds".to_string()),
+                };
+                Self {
+                    name: e.name(),
+                    extensions,
+                }
+            })
+            .collect()
+    }
+}
+
+// CSME 11+ uses one set of Huffman dictionaries across chipsets, loaded as
+// `cse-0.dict` (code) and `cse-1.dict` (data), see `huffman.rs`.
+pub const HUFFMAN_DICT: &str = "cse";
+const PAGE_SIZE: usize = 0x1000;
+const CHUNK_OFFSET_MASK: u32 = 0x01ff_ffff;
+
+// `ModuleAttributes::compression`
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_HUFFMAN: u8 = 1;
+const COMPRESSION_LZMA: u8 = 2;
+
+// see https://github.com/ptresearch/unME11
+/// A Huffman compressed file starts with a table of chunks, one per
+/// decompressed page, followed by the compressed pages themselves.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HuffmanFile {
+    pub chunks: Vec<u32>, // offset in the lower 25 bits, flags in the upper 7
+}
+
+impl HuffmanFile {
+    /// Parse the chunk table of a file that decompresses to `size` bytes, as
+    /// given by the uncompressed size in its module attributes.
+    pub fn new(data: &[u8], size: usize) -> Result<Self, String> {
+        let count = size.div_ceil(PAGE_SIZE);
+        let Some(t) = data.get(..count * 4) else {
+            return Err(format!(
+                "Huffman chunk table for {count} pages out of bounds"
+            ));
+        };
+        let chunks = t
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        Ok(Self { chunks })
+    }
+
+    /// Size of the decompressed file.
+    pub fn size(&self) -> usize {
+        self.chunks.len() * PAGE_SIZE
+    }
+
+    /// Decompress all pages. `data` is the file that the chunk offsets
+    /// refer to.
+    pub fn decompress(&self, data: &[u8], dicts: &Dictionaries) -> Result<Vec<u8>, String> {
+        // A chunk extends up to the next one.
+        let mut offsets: Vec<usize> = self
+            .chunks
+            .iter()
+            .map(|c| (c & CHUNK_OFFSET_MASK) as usize)
+            .collect();
+        offsets.sort();
+
+        let mut out = Vec::<u8>::with_capacity(self.size());
+        for c in &self.chunks {
+            let o = (c & CHUNK_OFFSET_MASK) as usize;
+            let flags = c >> 25;
+            // unpopulated pages
+            if *c == 0 {
+                out.resize(out.len() + PAGE_SIZE, 0);
+                continue;
+            }
+            let end = match offsets.iter().find(|x| **x > o) {
+                Some(e) => *e,
+                None => data.len(),
+            };
+            let Some(d) = data.get(o..end) else {
+                return Err(format!("Huffman chunk @ {o:08x} out of bounds"));
+            };
+            // NOTE: like in Gen 2, bit 30 appears to select the data dictionary.
+            let dict = dicts.get(HUFFMAN_DICT, ((flags >> 5) & 1) as usize)?;
+            out.extend(dict.decode(d, PAGE_SIZE)?);
+        }
+        Ok(out)
+    }
+}
+
+impl CPDMetadata {
+    /// The module attributes: compression, sizes and hash of the module.
+    pub fn module_attributes(&self) -> Option<(&ModuleAttributes, &Vec<u8>)> {
+        let Ok(exts) = &self.extensions else {
+            return None;
+        };
+        exts.iter().find_map(|e| match e {
+            Extension::ModuleAttributes(a, h) => Some((a, h)),
+            _ => None,
+        })
+    }
+
+    /// The process attributes: load address, sizes and entry point.
+    pub fn process_attributes(&self) -> Option<&ProcessAttributes> {
+        let Ok(exts) = &self.extensions else {
+            return None;
+        };
+        exts.iter().find_map(|e| match e {
+            Extension::ProcessAttributes(a, _) => Some(a),
+            _ => None,
+        })
+    }
+}
 
 impl CodePartitionDirectory {
-    pub fn new(data: Vec<u8>, offset: usize) -> Result<Self, String> {
-        let Ok((header, _)) = CPDHeader::read_from_prefix(&data) else {
-            return Err("could not parse CPD header".to_string());
+    /// Check each module against the hash in its metadata. The result per
+    /// module is whether the hash matches, or why it could not be checked.
+    pub fn verify_hashes(
+        &self,
+        image: &[u8],
+        dicts: &Dictionaries,
+    ) -> Vec<(String, Result<bool, String>)> {
+        self.metadata
+            .iter()
+            .map(|m| {
+                let n = m.name.trim_end_matches(".met").to_string();
+                let r = self.verify_hash(image, m, &n, dicts);
+                (n, r)
+            })
+            .collect()
+    }
+
+    fn verify_hash(
+        &self,
+        image: &[u8],
+        m: &CPDMetadata,
+        name: &str,
+        dicts: &Dictionaries,
+    ) -> Result<bool, String> {
+        let Some((attrs, h)) = m.module_attributes() else {
+            return Err("no module attributes".to_string());
         };
-        let n = header.part_name;
+        let Some(e) = self.entries.iter().find(|e| e.name() == name) else {
+            return Err("module not found".to_string());
+        };
+        // see https://github.com/platomav/MEAnalyzer
+        // Huffman compressed modules are hashed decompressed, the others as
+        // stored.
+        let d = match self.compression(e)? {
+            Compression::Huffman => self.file(image, e, dicts)?,
+            _ => {
+                let o = self.offset + e.offset() as usize;
+                let Some(d) = image.get(o..o + attrs.size as usize) else {
+                    return Err("module out of bounds".to_string());
+                };
+                d.to_vec()
+            }
+        };
+        hash::matches(&d, h)
+    }
+
+    /// The metadata describing the file `e`, if any.
+    pub fn metadata(&self, e: &CPDEntry) -> Option<&CPDMetadata> {
+        let n = format!("{}.met", e.name());
+        self.metadata.iter().find(|m| m.name == n)
+    }
+
+    /// The compression as per the module attributes in the metadata, which
+    /// the Huffman flag in the entry has to agree with. Files without
+    /// metadata are only compressed if the entry says so.
+    pub fn compression(&self, e: &CPDEntry) -> Result<Compression, String> {
+        let attrs = self.metadata(e).and_then(|m| m.module_attributes());
+        let c = match attrs.map(|(a, _)| a.compression) {
+            None if e.is_huffman() => Compression::Huffman,
+            None | Some(COMPRESSION_NONE) => Compression::Uncompressed,
+            Some(COMPRESSION_HUFFMAN) => Compression::Huffman,
+            Some(COMPRESSION_LZMA) => Compression::Lzma,
+            Some(c) => return Err(format!("unknown compression {c}")),
+        };
+        if e.is_huffman() != (c == Compression::Huffman) {
+            return Err(format!("{c:?} as per metadata, but Huffman flag in entry"));
+        }
+        Ok(c)
+    }
+
+    /// Get the contents of a file, decompressed to the uncompressed size in
+    /// its metadata if compressed. `image` is the whole flash image that
+    /// `offset` refers to.
+    pub fn file(
+        &self,
+        image: &[u8],
+        e: &CPDEntry,
+        dicts: &Dictionaries,
+    ) -> Result<Vec<u8>, String> {
+        let o = self.offset + e.offset() as usize;
+        let s = e.size as usize;
+        let Some(d) = image.get(o..o + s) else {
+            return Err(format!("file {} out of bounds", e.name()));
+        };
+        let c = self.compression(e)?;
+        if c == Compression::Uncompressed {
+            return Ok(d.to_vec());
+        }
+        let Some((a, _)) = self.metadata(e).and_then(|m| m.module_attributes()) else {
+            return Err(format!("no module attributes for {}", e.name()));
+        };
+        let size = a.uncompressed_size as usize;
+        match c {
+            Compression::Huffman => {
+                let mut m = HuffmanFile::new(d, size)?.decompress(d, dicts)?;
+                m.truncate(size);
+                Ok(m)
+            }
+            _ => lzma::decompress(d, Some(size)),
+        }
+    }
+
+    /// Get the module described by the metadata `m` as an ELF file, mapped
+    /// at the code base from its process attributes.
+    pub fn elf(
+        &self,
+        image: &[u8],
+        m: &CPDMetadata,
+        dicts: &Dictionaries,
+    ) -> Result<Vec<u8>, String> {
+        let Some(p) = m.process_attributes() else {
+            return Err("no process attributes".to_string());
+        };
+        let name = m.name.trim_end_matches(".met");
+        let Some(e) = self.entries.iter().find(|e| e.name() == name) else {
+            return Err("module not found".to_string());
+        };
+        let mut d = self.file(image, e, dicts)?;
+        if let Some((a, _)) = m.module_attributes() {
+            d.truncate(a.uncompressed_size as usize);
+        }
+        let base = p.code_base;
+        let size = p.uncompressed_size.max(d.len() as u32);
+        let Some(end) = base
+            .checked_add(size)
+            .and_then(|e| e.checked_add(p.bss_size))
+        else {
+            return Err(format!("image at {base:08x} exceeds the address space"));
+        };
+        // NOTE: We do not know where code ends and data begins, so the whole
+        // image is one segment, and assume that the BSS follows it.
+        let flags = PF_R | PF_W | PF_X;
+        let segments = [Segment::from_image(".text", &d, base, base, end, flags)];
+        Ok(elf::write(elf::EM_386, p.main_thread_entry, &segments))
+    }
+
+    pub fn new(data: Vec<u8>, offset: usize) -> Result<Self, String> {
+        let header = CPDHeader::new(&data)?;
+        let n = header.part_name();
         let name = match std::str::from_utf8(&n) {
             // some names are shorter than 4 bytes and padded with 0x0
             Ok(n) => n.trim_end_matches('\0').to_string(),
             Err(_) => format!("{:02x?}", n),
         };
         let mut entries = Vec::<CPDEntry>::new();
-        let header_size = if header.version_or_checksum == 0x00140102 {
-            HEADER_SIZE + 4
-        } else {
-            HEADER_SIZE
-        };
-        for e in 0..header.entries as usize {
-            let pos = header_size + e * 24;
-            let (mut entry, _) = CPDEntry::read_from_prefix(&data[pos..]).unwrap();
-            entry.offset &= OFFSET_MASK;
+        for e in 0..header.entries() as usize {
+            let pos = header.size() + e * ENTRY_SIZE;
+            let Ok((entry, _)) = CPDEntry::read_from_prefix(&data[pos.min(data.len())..]) else {
+                return Err(format!("could not parse CPD entry {e}"));
+            };
             entries.push(entry);
         }
 
-        let manifest = {
-            let name = format!("{}.man", name);
-            if let Some(e) = entries.iter().find(|e| e.name() == name) {
-                let b = &data[e.offset as usize..];
-                Manifest::new(b)
-            } else {
-                Err("no manifest found".to_string())
+        let checksum = match header.compute_checksum(&data)? {
+            c if c == header.checksum() => Ok(()),
+            c => Err(format!("checksum mismatch, computed {c:08x}")),
+        };
+
+        let manifests = CPDManifest::all(&data, &entries);
+        // The partition manifest is usually named after the partition.
+        let primary = format!("{name}.man");
+        let (manifest, signature) = match manifests
+            .iter()
+            .find(|m| m.name == primary)
+            .or_else(|| manifests.iter().find(|m| m.name.ends_with(".man")))
+        {
+            Some(m) => (m.manifest.clone(), m.signature.clone()),
+            None => {
+                let e = "no manifest found".to_string();
+                (Err(e.clone()), Err(e))
             }
         };
+        let hash_size = manifest.as_ref().map_or(32, |m| m.hash_size());
+        let metadata = CPDMetadata::all(&data, &entries, hash_size);
 
         let cpd = CodePartitionDirectory {
             header,
+            checksum,
             manifest,
+            signature,
+            manifests,
+            metadata,
             entries,
             offset,
             name: name.to_string(),
@@ -109,3 +624,80 @@ impl CodePartitionDirectory {
         Ok(cpd)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir::huffman::Dictionary;
+    use std::collections::BTreeMap;
+
+    // a header with a single entry, `FTPR.man` @ 0x30, 0x100 bytes
+    fn cpd(version: u8, checksum: &[u8]) -> Vec<u8> {
+        let header_length = if version == 1 { 0x10 } else { 0x14 };
+        let mut d = b"$CPD".to_vec();
+        d.extend_from_slice(&1u32.to_le_bytes());
+        d.extend_from_slice(&[version, 1, header_length]);
+        if version == 1 {
+            d.extend_from_slice(checksum);
+            d.extend_from_slice(b"FTPR");
+        } else {
+            d.extend_from_slice(&[0]);
+            d.extend_from_slice(b"FTPR");
+            d.extend_from_slice(checksum);
+        }
+        d.extend_from_slice(b"FTPR.man\0\0\0\0");
+        for w in [0x30u32, 0x100, 0] {
+            d.extend_from_slice(&w.to_le_bytes());
+        }
+        d
+    }
+
+    fn check(data: &[u8], expected: u32) {
+        let h = CPDHeader::new(data).unwrap();
+        assert_eq!(h.checksum(), expected);
+        assert_eq!(h.compute_checksum(data).unwrap(), expected);
+        let mut tampered = data.to_vec();
+        tampered[0x1c] ^= 1; // in the entry
+        assert_ne!(h.compute_checksum(&tampered).unwrap(), expected);
+        assert!(h.compute_checksum(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn checksum_v1() {
+        check(&cpd(1, &[0xdf]), 0xdf);
+    }
+
+    #[test]
+    fn checksum_v2() {
+        check(&cpd(2, &0x7855_5fadu32.to_le_bytes()), 0x7855_5fad);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn huffman_file() {
+        let code = Dictionary::parse(&format!("0 {}", "11".repeat(0x800))).unwrap();
+        let data = Dictionary::parse(&format!("1 {}", "22".repeat(0x1000))).unwrap();
+        let dicts = Dictionaries(BTreeMap::from([(
+            HUFFMAN_DICT.to_string(),
+            vec![code, data],
+        )]));
+
+        // a code page, an unpopulated page and a data page
+        let chunks = [12u32, 0, 13 | 1 << 30];
+        let mut f: Vec<u8> = chunks.iter().flat_map(|c| c.to_le_bytes()).collect();
+        f.extend_from_slice(&[0b0000_0000, 0b1000_0000]);
+
+        assert!(HuffmanFile::new(&f[..8], 0x2800).is_err());
+        let h = HuffmanFile::new(&f, 0x2800).unwrap();
+        assert_eq!(h.chunks, chunks);
+        let out = h.decompress(&f, &dicts).unwrap();
+        assert_eq!(out.len(), 3 * PAGE_SIZE);
+        assert!(out[..PAGE_SIZE].iter().all(|b| *b == 0x11));
+        assert!(out[PAGE_SIZE..2 * PAGE_SIZE].iter().all(|b| *b == 0));
+        assert!(out[2 * PAGE_SIZE..].iter().all(|b| *b == 0x22));
+    }
+}
@@ -0,0 +1,15 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384};
+
+/// Check `data` against a SHA-1, SHA-256 or SHA-384 hash, chosen by its size.
+/// Intel stores hashes, like the RSA keys, as little-endian integers, i.e.,
+/// in reverse byte order.
+pub fn matches(data: &[u8], hash: &[u8]) -> Result<bool, String> {
+    let h = match hash.len() {
+        20 => Sha1::digest(data).to_vec(),
+        32 => Sha256::digest(data).to_vec(),
+        48 => Sha384::digest(data).to_vec(),
+        l => return Err(format!("unsupported hash size {l}")),
+    };
+    Ok(h.iter().rev().eq(hash.iter()))
+}
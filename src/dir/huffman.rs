@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+// Intel ME Huffman compression works on pages: each page is encoded on its
+// own with a static dictionary that maps codes of up to 15 bits to byte
+// sequences. There are separate dictionaries for code and data pages, and
+// they differ between chipset generations.
+//
+// The dictionaries are not part of the images, and we do not ship them.
+// They are read from text files, one line per code:
+//
+//     <code as binary digits, MSB first> <bytes as hex>
+//     0000110 00000000
+//     00111011 8b45
+//
+// Lines starting with '#' are comments.
+// see https://io.netgarage.org/me/ (unhuffme) and
+// https://github.com/skochinsky/me-tools me_unpack.py
+const MAX_CODE_LEN: u8 = 15;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Dictionary {
+    codes: HashMap<(u8, u32), Vec<u8>>,
+    min_len: u8,
+    max_len: u8,
+}
+
+impl Dictionary {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut d = Self {
+            min_len: MAX_CODE_LEN,
+            ..Self::default()
+        };
+        for (n, l) in text.lines().enumerate() {
+            let l = l.trim();
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            let err = || format!("line {}: cannot parse {l}", n + 1);
+            let Some((code, val)) = l.split_once(char::is_whitespace) else {
+                return Err(err());
+            };
+            let len = code.len() as u8;
+            if len == 0 || len > MAX_CODE_LEN {
+                return Err(err());
+            }
+            let Ok(c) = u32::from_str_radix(code, 2) else {
+                return Err(err());
+            };
+            let val = val.trim();
+            if val.len() % 2 != 0 {
+                return Err(err());
+            }
+            let bytes: Result<Vec<u8>, _> = (0..val.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&val[i..i + 2], 16))
+                .collect();
+            let Ok(bytes) = bytes else {
+                return Err(err());
+            };
+            d.min_len = d.min_len.min(len);
+            d.max_len = d.max_len.max(len);
+            d.codes.insert((len, c), bytes);
+        }
+        if d.codes.is_empty() {
+            return Err("empty Huffman dictionary".to_string());
+        }
+        Ok(d)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let p = path.display();
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {p}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("{p}: {e}"))
+    }
+
+    /// Decode a single page until `size` bytes are produced.
+    pub fn decode(&self, data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+        let mut out = Vec::<u8>::with_capacity(size);
+        let mut bit = 0;
+        let total = data.len() * 8;
+        while out.len() < size {
+            let mut code = 0u32;
+            let mut len = 0u8;
+            loop {
+                if bit >= total {
+                    let o = out.len();
+                    return Err(format!("Huffman data ends after 0x{o:x} bytes"));
+                }
+                let b = (data[bit / 8] >> (7 - bit % 8)) & 1;
+                code = (code << 1) | b as u32;
+                len += 1;
+                bit += 1;
+                if len >= self.min_len {
+                    if let Some(v) = self.codes.get(&(len, code)) {
+                        out.extend_from_slice(v);
+                        break;
+                    }
+                }
+                if len >= self.max_len {
+                    let o = out.len();
+                    return Err(format!("invalid Huffman code {code:b} at 0x{o:x}"));
+                }
+            }
+        }
+        out.truncate(size);
+        Ok(out)
+    }
+}
+
+/// Dictionaries per chipset, each with the code and data dictionary.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Dictionaries(pub BTreeMap<String, Vec<Dictionary>>);
+
+impl Dictionaries {
+    /// Load all `<chipset>-<n>.dict` files from a directory, where `n` is
+    /// the dictionary index, 0 for code and 1 for data.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let d = dir.display();
+        let rd = fs::read_dir(dir).map_err(|e| format!("cannot read {d}: {e}"))?;
+        let mut dicts = BTreeMap::<String, Vec<Dictionary>>::new();
+        let mut files: Vec<_> = rd.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        files.sort();
+        for f in files {
+            if f.extension().is_none_or(|e| e != "dict") {
+                continue;
+            }
+            let Some(stem) = f.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let Some((chipset, n)) = stem.rsplit_once('-') else {
+                continue;
+            };
+            let Ok(n) = n.parse::<usize>() else {
+                continue;
+            };
+            let v = dicts.entry(chipset.to_string()).or_default();
+            if v.len() <= n {
+                v.resize(n + 1, Dictionary::default());
+            }
+            v[n] = Dictionary::load(&f)?;
+        }
+        Ok(Self(dicts))
+    }
+
+    pub fn get(&self, chipset: &str, index: usize) -> Result<&Dictionary, String> {
+        match self.0.get(chipset).and_then(|v| v.get(index)) {
+            Some(d) if !d.codes.is_empty() => Ok(d),
+            _ => Err(format!("no Huffman dictionary {index} for {chipset}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DICT: &str = "
+        # code value
+        1  00
+        00 aa
+        01 bbcc
+    ";
+
+    #[test]
+    fn decode() {
+        let d = Dictionary::parse(DICT).unwrap();
+        // 1 00 01 1 00
+        let page = [0b1000_1100];
+        assert_eq!(d.decode(&page, 5).unwrap(), [0x00, 0xaa, 0xbb, 0xcc, 0x00]);
+        assert_eq!(d.decode(&page, 4).unwrap(), [0x00, 0xaa, 0xbb, 0xcc]);
+        assert_eq!(d.decode(&page, 6).unwrap()[5], 0xaa);
+        assert!(d.decode(&page, 7).is_err());
+    }
+
+    #[test]
+    fn invalid_code() {
+        let d = Dictionary::parse("0 00\n10 11").unwrap();
+        assert!(d.decode(&[0b1100_0000], 1).is_err());
+        assert!(Dictionary::parse("2 00").is_err());
+        assert!(Dictionary::parse("0 abc").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use lzma_rs::decompress::{Options, UnpackedSize};
+
+// Intel uses the plain .lzma format: properties, dictionary size and the
+// 64-bit uncompressed size, followed by the range coded data. All modules
+// seen so far share the same properties and dictionary size.
+pub const SIG: [u8; 4] = [0x36, 0x00, 0x40, 0x00];
+const HEADER_SIZE: usize = 13;
+// Some streams carry 3 extra zero bytes after the first byte of range
+// coded data, which is always zero itself.
+// see https://github.com/platomav/MEAnalyzer
+const PADDING: std::ops::Range<usize> = HEADER_SIZE + 1..HEADER_SIZE + 4;
+
+pub fn is_lzma(data: &[u8]) -> bool {
+    data.starts_with(&SIG)
+}
+
+fn unpack(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::<u8>::new();
+    let opts = Options {
+        unpacked_size: UnpackedSize::ReadFromHeader,
+        ..Options::default()
+    };
+    let mut r = data;
+    match lzma_rs::lzma_decompress_with_options(&mut r, &mut out, &opts) {
+        Ok(()) => Ok(out),
+        Err(e) => Err(format!("LZMA: {e}")),
+    }
+}
+
+/// Decompress an LZMA stream in Intel's variant of the format, checking
+/// the result against the expected size if given.
+pub fn decompress(data: &[u8], expected: Option<usize>) -> Result<Vec<u8>, String> {
+    if data.len() < HEADER_SIZE + 4 {
+        return Err("LZMA stream too short".to_string());
+    }
+    let out = match unpack(data) {
+        Ok(o) => o,
+        Err(e) => {
+            if data[HEADER_SIZE..PADDING.end] != [0; 4] {
+                return Err(e);
+            }
+            let mut d = data[..PADDING.start].to_vec();
+            d.extend_from_slice(&data[PADDING.end..]);
+            unpack(&d)?
+        }
+    };
+    match expected {
+        Some(s) if s != out.len() => Err(format!(
+            "LZMA: got 0x{:x} bytes, expected 0x{s:x}",
+            out.len()
+        )),
+        _ => Ok(out),
+    }
+}
@@ -4,24 +4,16 @@ use zerocopy::FromBytes;
 use zerocopy_derive::{FromBytes, IntoBytes};
 
 use crate::ver::Version;
+pub use date::Date;
 
-const VENDOR_INTEL: u32 = 0x8086;
-const MANIFEST2_MAGIC: &[u8] = b"$MN2";
+pub mod date;
+pub mod ext;
+pub mod keys;
+pub mod rsa;
 
-#[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
-#[repr(C)]
-pub struct Date {
-    day: u8,
-    month: u8,
-    year: u16,
-}
-
-impl Display for Date {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Date { year, month, day } = self;
-        write!(f, "{year:04x}-{month:02x}-{day:02x}")
-    }
-}
+const VENDOR_INTEL: u32 = 0x8086;
+pub const MANIFEST_MAGIC: &[u8] = b"$MAN"; // ME 2-5
+pub const MANIFEST2_MAGIC: &[u8] = b"$MN2";
 
 #[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
 #[repr(C)]
@@ -52,6 +44,33 @@ impl Display for HeaderVersion {
     }
 }
 
+// see https://github.com/platomav/MEAnalyzer MN2_Manifest_R0
+const MANIFEST_TYPE: u16 = 4;
+const FLAG_PV: u32 = 1 << 0; // production version
+const FLAG_PRE_PRODUCTION: u32 = 1 << 30;
+const FLAG_DEBUG: u32 = 1 << 31;
+// see https://github.com/platomav/MEAnalyzer CSE_Ext_0E KeyType, 1 RoT, 2 OEM
+const KEY_TYPE_OEM: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ManifestKind {
+    CodePartition,
+    KeyManifest,
+    OemKeyManifest,
+    Unknown(u16, u16), // type and subtype
+}
+
+impl Display for ManifestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CodePartition => write!(f, "code partition manifest"),
+            Self::KeyManifest => write!(f, "key manifest"),
+            Self::OemKeyManifest => write!(f, "OEM key manifest"),
+            Self::Unknown(t, s) => write!(f, "manifest type {t}.{s}"),
+        }
+    }
+}
+
 // https://github.com/skochinsky/me-tools me_unpack.py MeManifestHeader
 #[derive(IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
 #[repr(C)]
@@ -65,80 +84,210 @@ pub struct Header {
     pub date: Date,
     pub size: u32, // in dwords, dword size is 32bit
     pub magic: [u8; 4],
-    // NOTE: only for Gen 2 ME firmware
+    // NOTE: only for Gen 1 and 2 ME firmware
     pub entries: u32,
     pub version: Version,
-    xx0: u32, // e.g. 0x0000_0001
-    _30: u32, // e.g. all zero
-    xxx: u32, // e.g. 0x0000_0003
+    pub svn: u32,   // security version number, e.g. 0x0000_0001
+    pub svn_8: u32, // ME 8 specific SVN, e.g. all zero
+    pub vcn: u32,   // version control number, e.g. 0x0000_0003
     #[serde(with = "serde_bytes")]
-    _38: [u8; 0x40], // e.g. all zero
+    pub _38: [u8; 0x40], // reserved, e.g. all zero
     pub key_size: u32, // in dwords
     pub scratch_size: u32,
 }
 
+impl Header {
+    /// Length of the header, key, exponent and signature in bytes
+    pub fn length(&self) -> usize {
+        self.header_len as usize * 4
+    }
+
+    /// The kind as far as the header tells; key manifests share the type of
+    /// code partition manifests and are only told apart by their extensions.
+    pub fn kind(&self) -> ManifestKind {
+        match (self.mod_type, self.mod_subtype) {
+            (MANIFEST_TYPE, 0) => ManifestKind::CodePartition,
+            (t, s) => ManifestKind::Unknown(t, s),
+        }
+    }
+
+    /// Signed with a debug key rather than a production key
+    pub fn is_debug(&self) -> bool {
+        self.flags & FLAG_DEBUG != 0
+    }
+
+    /// Marked as production version (PV)
+    pub fn is_pv(&self) -> bool {
+        self.flags & FLAG_PV != 0
+    }
+
+    /// Marked as pre-production firmware
+    pub fn is_pre_production(&self) -> bool {
+        self.flags & FLAG_PRE_PRODUCTION != 0
+    }
+}
+
 impl Display for Header {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let hver = self.header_ver;
         let hlen = self.header_len;
         let ver = self.version;
         let date = self.date;
+        let valid = if date.is_valid() { "" } else { " (invalid)" };
         let ven = self.vendor;
         let e = self.entries;
+        let svn = self.svn;
+        let vcn = self.vcn;
+        let sig = if self.is_debug() {
+            "debug"
+        } else {
+            "production"
+        };
+        let pv = if self.is_pv() { ", PV" } else { "" };
+        let pre = if self.is_pre_production() {
+            ", pre-production"
+        } else {
+            ""
+        };
         write!(
             f,
-            "{hver} {hlen:04x} vendor {ven}, version {ver} {date}, {e} entries"
+            "{hver} {hlen:04x} vendor {ven}, version {ver} {date}{valid}, {e} entries, SVN {svn}, VCN {vcn}, {sig} signed{pv}{pre}"
         )
     }
 }
 
 const HEADER_SIZE: usize = core::mem::size_of::<Header>();
-const KEY_SIZE: usize = 0x100;
+const EXP_SIZE: usize = 4;
+const RSA2048_SIZE: usize = 0x100;
+const RSA3072_SIZE: usize = 0x180;
 
-#[derive(Serialize, Deserialize, IntoBytes, FromBytes, Clone, Copy, Debug)]
-#[repr(C)]
+/// A manifest with its RSA public key and signature, sized per the header:
+/// 0x100 bytes each for RSA-2048, 0x180 for RSA-3072 in CSME 12+.
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Manifest {
     pub header: Header,
+    pub kind: ManifestKind,
     #[serde(with = "serde_bytes")]
-    pub rsa_pub_key: [u8; KEY_SIZE],
+    pub rsa_pub_key: Vec<u8>,
     pub rsa_pub_exp: u32,
     #[serde(with = "serde_bytes")]
-    pub rsa_sig: [u8; KEY_SIZE],
+    pub rsa_sig: Vec<u8>,
 }
 
-pub const MANIFEST_SIZE: usize = core::mem::size_of::<Manifest>();
-
 impl<'a> Manifest {
     pub fn new(data: &'a [u8]) -> Result<Self, String> {
-        let (header, _) = Header::read_from_prefix(data).unwrap();
+        let Ok((header, _)) = Header::read_from_prefix(data) else {
+            return Err("cannot parse manifest header".to_string());
+        };
 
-        if header.magic != *MANIFEST2_MAGIC {
+        if header.magic != *MANIFEST2_MAGIC && header.magic != *MANIFEST_MAGIC {
             let err = format!("manifest magic not found, got: {:02x?}", header.magic);
             return Err(err);
         }
 
-        let o = HEADER_SIZE;
-        let rsa_pub_key: [u8; KEY_SIZE] = data[o..o + KEY_SIZE].try_into().unwrap();
-        let o = o + KEY_SIZE;
-        let (rsa_pub_exp, _) = u32::read_from_prefix(&data[o..o + 4]).unwrap();
-        let o = o + 4;
-        let rsa_sig: [u8; KEY_SIZE] = data[o..o + KEY_SIZE].try_into().unwrap();
+        let key_size = header.key_size as usize * 4;
+        let size = HEADER_SIZE + key_size + EXP_SIZE + key_size;
+        let len = header.length();
+        if key_size == 0 || size != len {
+            return Err(format!(
+                "manifest key size 0x{key_size:x} does not match header length 0x{len:x}"
+            ));
+        }
+        let Some(d) = data.get(HEADER_SIZE..size) else {
+            return Err("manifest out of bounds".to_string());
+        };
+        let rsa_pub_key = d[..key_size].to_vec();
+        let o = key_size;
+        let (rsa_pub_exp, _) = u32::read_from_prefix(&d[o..o + EXP_SIZE]).unwrap();
+        let o = o + EXP_SIZE;
+        let rsa_sig = d[o..o + key_size].to_vec();
 
-        let m = Self {
+        let mut m = Self {
             header,
+            kind: header.kind(),
             rsa_pub_key,
             rsa_pub_exp,
             rsa_sig,
         };
+        let key_type = m.extensions(data).find_map(|e| match e {
+            Ok(ext::Extension::KeyManifest(h, _)) => Some(h.key_type),
+            _ => None,
+        });
+        if m.kind == ManifestKind::CodePartition {
+            m.kind = match key_type {
+                Some(KEY_TYPE_OEM) => ManifestKind::OemKeyManifest,
+                Some(_) => ManifestKind::KeyManifest,
+                None => m.kind,
+            };
+        }
 
         Ok(m)
     }
+
+    /// Hash over the public key and exponent, identifying the key.
+    pub fn key_hash(&self) -> Vec<u8> {
+        keys::key_hash(&self.rsa_pub_key, self.rsa_pub_exp)
+    }
+
+    /// Size of the hashes in the extensions, SHA-384 with RSA-3072 keys,
+    /// SHA-256 otherwise.
+    pub fn hash_size(&self) -> usize {
+        match self.rsa_pub_key.len() {
+            RSA3072_SIZE => 48,
+            _ => 32,
+        }
+    }
+
+    /// Look up the signing key in the bundled table of known keys.
+    pub fn known_key(&self) -> Option<&'static keys::KnownKey> {
+        keys::KeyTable::bundled().find(&self.key_hash())
+    }
+
+    /// Verify the signature over the header and everything following the
+    /// signature, up to the size given in the header. `data` is the
+    /// manifest, as passed to `new`.
+    pub fn verify(&self, data: &[u8]) -> Result<bool, String> {
+        let start = self.header.length();
+        let end = self.header.size as usize * 4;
+        let (Some(h), Some(rest)) = (data.get(..HEADER_SIZE), data.get(start..end)) else {
+            return Err("signed data out of bounds".to_string());
+        };
+        let signed = [h, rest].concat();
+        let alg = match (&self.header.magic[..], self.rsa_pub_key.len()) {
+            (MANIFEST_MAGIC, _) => rsa::HashAlgorithm::Sha1,
+            (_, RSA2048_SIZE) => rsa::HashAlgorithm::Sha256,
+            (_, RSA3072_SIZE) => rsa::HashAlgorithm::Sha384,
+            (_, l) => return Err(format!("unexpected RSA key size 0x{l:x}")),
+        };
+        rsa::verify(
+            alg,
+            &self.rsa_pub_key,
+            self.rsa_pub_exp,
+            &self.rsa_sig,
+            &signed,
+        )
+    }
+
+    /// Iterate over the extensions after the signature, CSME 11+ only.
+    /// `data` is the manifest, as passed to `new`.
+    pub fn extensions(&self, data: &'a [u8]) -> ext::Extensions<'a> {
+        let start = self.header.length();
+        let end = (self.header.size as usize * 4).min(data.len());
+        ext::Extensions::new(data.get(start..end).unwrap_or_default(), self.hash_size())
+    }
 }
 
 impl Display for Manifest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let h = self.header;
+        let k = self.kind;
+        let bits = self.rsa_pub_key.len() * 8;
         let exp = self.rsa_pub_exp;
-        write!(f, "{h}, RSA exp {exp}")
+        let kh = keys::to_hex(&self.key_hash());
+        let key = match self.known_key() {
+            Some(k) => format!("{k}"),
+            None => "unknown key".to_string(),
+        };
+        write!(f, "{k} {h}, RSA-{bits} exp {exp}, {key} {kh}")
     }
 }
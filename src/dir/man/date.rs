@@ -0,0 +1,189 @@
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::str::FromStr;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use zerocopy_derive::{FromBytes, IntoBytes};
+
+/// A build date, stored as BCD, e.g. 0x2024_01_31 for 2024-01-31.
+#[derive(IntoBytes, FromBytes, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct Date {
+    day: u8,
+    month: u8,
+    year: u16,
+}
+
+fn from_bcd(v: u16) -> Option<u16> {
+    (0..4)
+        .rev()
+        .try_fold(0, |acc, i| match (v >> (i * 4)) & 0xf {
+            d if d < 10 => Some(acc * 10 + d),
+            _ => None,
+        })
+}
+
+fn to_bcd(v: u16) -> u16 {
+    (0..4).fold(0, |acc, i| acc | ((v / 10u16.pow(i)) % 10) << (i * 4))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    pub fn new(year: u16, month: u8, day: u8) -> Result<Self, String> {
+        // Check the ranges first, BCD would drop the excess digits.
+        if year > 9999 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(format!("invalid date {year}-{month}-{day}"));
+        }
+        let d = Self {
+            year: to_bcd(year),
+            month: to_bcd(month as u16) as u8,
+            day: to_bcd(day as u16) as u8,
+        };
+        d.decode()?;
+        Ok(d)
+    }
+
+    /// Decode to year, month and day, checking that the date exists.
+    pub fn decode(&self) -> Result<(u16, u8, u8), String> {
+        let err = || format!("invalid date {self}");
+        let y = from_bcd(self.year).ok_or_else(err)?;
+        let m = from_bcd(self.month as u16).ok_or_else(err)? as u8;
+        let d = from_bcd(self.day as u16).ok_or_else(err)? as u8;
+        if !(1..=12).contains(&m) || d == 0 || d > days_in_month(y, m) {
+            return Err(err());
+        }
+        Ok((y, m, d))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.decode().is_ok()
+    }
+}
+
+// BCD sorts like the decimal values, so valid dates sort chronologically.
+impl Ord for Date {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.year, self.month, self.day).cmp(&(other.year, other.month, other.day))
+    }
+}
+
+impl PartialOrd for Date {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Date { year, month, day } = self;
+        write!(f, "{year:04x}-{month:02x}-{day:02x}")
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    /// Parse an ISO-8601 date, YYYY-MM-DD.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid date {s}");
+        let mut parts = s.splitn(3, '-');
+        let (Some(y), Some(m), Some(d)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(err());
+        };
+        let y = y.parse::<u16>().map_err(|_| err())?;
+        let m = m.parse::<u8>().map_err(|_| err())?;
+        let d = d.parse::<u8>().map_err(|_| err())?;
+        Self::new(y, m, d)
+    }
+}
+
+impl From<Date> for u32 {
+    fn from(d: Date) -> Self {
+        (d.year as u32) << 16 | (d.month as u32) << 8 | d.day as u32
+    }
+}
+
+impl From<u32> for Date {
+    /// The raw BCD value, e.g. 0x2024_01_31, which need not be valid.
+    fn from(v: u32) -> Self {
+        Self {
+            year: (v >> 16) as u16,
+            month: (v >> 8) as u8,
+            day: v as u8,
+        }
+    }
+}
+
+// Dates serialize as ISO-8601 strings. Invalid dates from corrupted images
+// serialize as their raw BCD value instead, so that they can be read back.
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        if self.is_valid() {
+            s.collect_str(self)
+        } else {
+            s.serialize_u32((*self).into())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Iso(String),
+    Raw(u32),
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match Repr::deserialize(d)? {
+            Repr::Iso(s) => s.parse().map_err(de::Error::custom),
+            Repr::Raw(v) => Ok(v.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!("2024-01-31".parse(), Date::new(2024, 1, 31));
+        for s in [
+            "2024-257-01",
+            "2024-01-257",
+            "2024-101-01",
+            "2024-01-131",
+            "2024-00-01",
+            "2024-01-00",
+            "10000-01-01",
+            "2024-13-01",
+            "2023-02-29",
+            "2024-01",
+        ] {
+            assert!(s.parse::<Date>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let d = Date::new(2024, 2, 29).unwrap();
+        let j = serde_json::to_string(&d).unwrap();
+        assert_eq!(j, "\"2024-02-29\"");
+        assert_eq!(serde_json::from_str::<Date>(&j).unwrap(), d);
+
+        let d = Date::from(0x2024_1331);
+        assert!(!d.is_valid());
+        let j = serde_json::to_string(&d).unwrap();
+        assert_eq!(serde_json::from_str::<Date>(&j).unwrap(), d);
+    }
+}
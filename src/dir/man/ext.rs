@@ -0,0 +1,404 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::str::from_utf8;
+use zerocopy::FromBytes;
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
+
+// CSME 11+ manifests and metadata (.met) files carry a chain of extensions,
+// each starting with its type and length, the latter including the header.
+// see https://github.com/LongSoft/UEFITool common/me.h and
+// https://github.com/platomav/MEAnalyzer CSE_Ext_*
+pub const SYSTEM_INFO: u32 = 0;
+pub const PARTITION_INFO: u32 = 3;
+pub const PROCESS_ATTRIBUTES: u32 = 5;
+pub const MODULE_ATTRIBUTES: u32 = 10;
+pub const KEY_MANIFEST: u32 = 14;
+pub const SIGNED_PACKAGE_INFO: u32 = 15;
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ExtHeader {
+    pub ext_type: u32,
+    pub length: u32,
+}
+
+const EXT_HEADER_SIZE: usize = core::mem::size_of::<ExtHeader>();
+
+fn name(n: &[u8]) -> String {
+    match from_utf8(n) {
+        Ok(n) => n.trim_end_matches('\0').to_string(),
+        Err(_) => format!("{n:02x?}"),
+    }
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct SystemInfo {
+    pub min_uma_size: u32,
+    pub chipset_version: u32,
+    pub image_hash: [u8; 32],
+    pub pageable_uma_size: u32,
+    pub _reserved0: u64,
+    pub _reserved1: u32,
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct IndependentPartition {
+    pub name: [u8; 4],
+    pub version: u32,
+    pub user_id: u16,
+    pub _reserved: u16,
+}
+
+/// The partition info, with the partition hash after `length` taken out,
+/// see `Extension::PartitionInfo`.
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PartitionInfo {
+    pub name: [u8; 4],
+    pub length: u32,
+    pub vcn: u32,
+    pub version: u32,
+    pub data_format_version: u32,
+    pub instance_id: u32,
+    pub flags: u32,
+    pub _reserved: [u8; 20],
+}
+
+const PARTITION_HASH_OFFSET: usize = 8;
+
+/// A module listed in the partition info, followed by the hash of its
+/// metadata.
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ModuleInfo {
+    pub name: [u8; 12],
+    pub module_type: u8,
+    pub _reserved: [u8; 3],
+    pub metadata_size: u32,
+}
+
+impl ModuleInfo {
+    pub fn name(&self) -> String {
+        name(&self.name)
+    }
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct ProcessAttributes {
+    pub flags: u32,
+    pub main_thread_id: u32,
+    pub code_base: u32,
+    pub uncompressed_size: u32,
+    pub cm0_heap_size: u32,
+    pub bss_size: u32,
+    pub default_heap_size: u32,
+    pub main_thread_entry: u32,
+    pub allowed_sys_calls: [u32; 3],
+    pub user_id: u16,
+    pub _reserved0: u32,
+    pub _reserved1: u16,
+    pub _reserved2: u64,
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ModuleAttributes {
+    pub compression: u8, // 0 uncompressed, 1 Huffman, 2 LZMA
+    pub _reserved: [u8; 3],
+    pub uncompressed_size: u32,
+    pub size: u32,
+    pub module_id: u32,
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct KeyManifest {
+    pub key_type: u32,
+    pub key_svn: u32,
+    pub oem_id: u16,
+    pub key_id: u8,
+    pub _reserved0: u8,
+    pub _reserved1: [u32; 4],
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct KeyManifestEntry {
+    pub usage_bitmap: [u64; 2],
+    pub _reserved: [u32; 4],
+    pub flags: u8,
+    pub hash_algorithm: u8,
+    pub hash_size: u16,
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct SignedPackageInfo {
+    pub name: [u8; 4],
+    pub vcn: u32,
+    pub usage_bitmap: [u8; 16],
+    pub svn: u32,
+    pub _reserved: [u8; 16],
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct SignedPackageModule {
+    pub name: [u8; 12],
+    pub module_type: u8,
+    pub hash_algorithm: u8,
+    pub hash_size: u16,
+    pub metadata_size: u32,
+}
+
+impl SignedPackageModule {
+    pub fn name(&self) -> String {
+        name(&self.name)
+    }
+}
+
+/// An entry followed by a hash
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Hashed<E> {
+    pub entry: E,
+    #[serde(with = "serde_bytes")]
+    pub hash: Vec<u8>,
+}
+
+trait HashSize {
+    /// Size of the hash following the entry, if the entry gives it
+    fn hash_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl HashSize for ModuleInfo {}
+
+impl HashSize for KeyManifestEntry {
+    fn hash_size(&self) -> Option<usize> {
+        Some(self.hash_size as usize)
+    }
+}
+
+impl HashSize for SignedPackageModule {
+    fn hash_size(&self) -> Option<usize> {
+        Some(self.hash_size as usize)
+    }
+}
+
+/// Extensions with a fixed part, most followed by a list of entries.
+/// Hashes without a size of their own are as long as the manifest hash,
+/// i.e., SHA-256 or SHA-384 for CSME 12+.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Extension {
+    SystemInfo(SystemInfo, Vec<IndependentPartition>),
+    PartitionInfo(PartitionInfo, Vec<u8>, Vec<Hashed<ModuleInfo>>), // hash of the partition
+    ProcessAttributes(ProcessAttributes, Vec<u16>),                 // group IDs
+    ModuleAttributes(ModuleAttributes, Vec<u8>),                    // hash of the module
+    KeyManifest(KeyManifest, Vec<Hashed<KeyManifestEntry>>),
+    SignedPackageInfo(SignedPackageInfo, Vec<Hashed<SignedPackageModule>>),
+    Unknown(u32, Vec<u8>),
+}
+
+// Parse the fixed part and as many entries as fit into the rest.
+fn parse<H: FromBytes, E: FromBytes>(data: &[u8]) -> Result<(H, Vec<E>), String> {
+    let Ok((h, rest)) = H::read_from_prefix(data) else {
+        return Err("extension too short".to_string());
+    };
+    let size = core::mem::size_of::<E>();
+    let entries = rest
+        .chunks_exact(size)
+        .filter_map(|c| E::read_from_bytes(c).ok())
+        .collect();
+    Ok((h, entries))
+}
+
+// Parse the fixed part and the entries, each followed by a hash of the size
+// given in the entry, or else `hash_size`.
+fn parse_hashed<H: FromBytes, E: FromBytes + HashSize>(
+    data: &[u8],
+    hash_size: usize,
+) -> Result<(H, Vec<Hashed<E>>), String> {
+    let Ok((h, mut rest)) = H::read_from_prefix(data) else {
+        return Err("extension too short".to_string());
+    };
+    let mut entries = Vec::<Hashed<E>>::new();
+    while let Ok((entry, r)) = E::read_from_prefix(rest) {
+        let s = entry.hash_size().unwrap_or(hash_size);
+        let Some(hash) = r.get(..s) else {
+            return Err(format!(
+                "extension entry hash of 0x{s:x} bytes out of bounds"
+            ));
+        };
+        let hash = hash.to_vec();
+        entries.push(Hashed { entry, hash });
+        rest = &r[s..];
+    }
+    Ok((h, entries))
+}
+
+impl Extension {
+    /// Parse an extension from its data, following the header. `hash_size`
+    /// is the size of the hashes that do not come with their own size.
+    pub fn new(ext_type: u32, data: &[u8], hash_size: usize) -> Result<Self, String> {
+        let e = match ext_type {
+            SYSTEM_INFO => {
+                let (h, e) = parse(data)?;
+                Self::SystemInfo(h, e)
+            }
+            PARTITION_INFO => {
+                let o = PARTITION_HASH_OFFSET;
+                let Some(hash) = data.get(o..o + hash_size) else {
+                    return Err("extension too short".to_string());
+                };
+                let d = [&data[..o], &data[o + hash_size..]].concat();
+                let (h, e) = parse_hashed(&d, hash_size)?;
+                Self::PartitionInfo(h, hash.to_vec(), e)
+            }
+            PROCESS_ATTRIBUTES => {
+                let (h, e) = parse(data)?;
+                Self::ProcessAttributes(h, e)
+            }
+            MODULE_ATTRIBUTES => {
+                let (h, e) = parse(data)?;
+                Self::ModuleAttributes(h, e)
+            }
+            KEY_MANIFEST => {
+                let (h, e) = parse_hashed(data, hash_size)?;
+                Self::KeyManifest(h, e)
+            }
+            SIGNED_PACKAGE_INFO => {
+                let (h, e) = parse_hashed(data, hash_size)?;
+                Self::SignedPackageInfo(h, e)
+            }
+            t => Self::Unknown(t, data.to_vec()),
+        };
+        Ok(e)
+    }
+
+    pub fn ext_type(&self) -> u32 {
+        match self {
+            Self::SystemInfo(..) => SYSTEM_INFO,
+            Self::PartitionInfo(..) => PARTITION_INFO,
+            Self::ProcessAttributes(..) => PROCESS_ATTRIBUTES,
+            Self::ModuleAttributes(..) => MODULE_ATTRIBUTES,
+            Self::KeyManifest(..) => KEY_MANIFEST,
+            Self::SignedPackageInfo(..) => SIGNED_PACKAGE_INFO,
+            Self::Unknown(t, _) => *t,
+        }
+    }
+}
+
+impl Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let t = self.ext_type();
+        match self {
+            Self::SystemInfo(h, e) => {
+                let u = h.min_uma_size;
+                let c = h.chipset_version;
+                let n = e.len();
+                write!(
+                    f,
+                    "{t:2} system info, UMA {u:08x}, chipset {c:08x}, {n} partitions"
+                )
+            }
+            Self::PartitionInfo(h, _, e) => {
+                let p = name(&h.name);
+                let l = h.length;
+                let v = h.version;
+                let n = e.len();
+                write!(
+                    f,
+                    "{t:2} partition info {p}, size {l:08x}, version {v:08x}, {n} modules"
+                )
+            }
+            Self::ProcessAttributes(h, _) => {
+                let b = h.code_base;
+                let s = h.uncompressed_size;
+                let e = h.main_thread_entry;
+                write!(
+                    f,
+                    "{t:2} process attributes, base {b:08x}, size {s:08x}, entry {e:08x}"
+                )
+            }
+            Self::ModuleAttributes(h, hash) => {
+                let c = h.compression;
+                let u = h.uncompressed_size;
+                let s = h.size;
+                let hl = hash.len() * 8;
+                write!(f, "{t:2} module attributes, compression {c}, size {s:08x} -> {u:08x}, {hl} bit hash")
+            }
+            Self::KeyManifest(h, e) => {
+                let k = h.key_type;
+                let o = h.oem_id;
+                let n = e.len();
+                write!(f, "{t:2} key manifest, type {k}, OEM {o:04x}, {n} keys")
+            }
+            Self::SignedPackageInfo(h, e) => {
+                let p = name(&h.name);
+                let v = h.vcn;
+                let s = h.svn;
+                let n = e.len();
+                write!(
+                    f,
+                    "{t:2} signed package info {p}, VCN {v}, SVN {s}, {n} modules"
+                )
+            }
+            Self::Unknown(_, d) => {
+                let l = d.len();
+                write!(f, "{t:2} unknown, 0x{l:x} bytes")
+            }
+        }
+    }
+}
+
+/// Iterate over the extensions in `data`, stopping at the first error.
+pub struct Extensions<'a> {
+    data: &'a [u8],
+    hash_size: usize,
+    done: bool,
+}
+
+impl<'a> Extensions<'a> {
+    pub fn new(data: &'a [u8], hash_size: usize) -> Self {
+        Self {
+            data,
+            hash_size,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Extensions<'_> {
+    type Item = Result<Extension, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.data.len() < EXT_HEADER_SIZE {
+            return None;
+        }
+        let (h, _) = ExtHeader::read_from_prefix(self.data).unwrap();
+        let t = h.ext_type;
+        let l = h.length as usize;
+        // erased flash or padding
+        if t == 0xffff_ffff {
+            return None;
+        }
+        if l < EXT_HEADER_SIZE || l > self.data.len() {
+            self.done = true;
+            return Some(Err(format!("extension {t} has invalid length 0x{l:x}")));
+        }
+        let d = &self.data[EXT_HEADER_SIZE..l];
+        self.data = &self.data[l..];
+        let e = Extension::new(t, d, self.hash_size);
+        self.done = e.is_err();
+        Some(e)
+    }
+}
+
+/// Parse all extensions in `data`.
+pub fn parse_all(data: &[u8], hash_size: usize) -> Result<Vec<Extension>, String> {
+    Extensions::new(data, hash_size).collect()
+}
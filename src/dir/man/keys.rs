@@ -0,0 +1,124 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// The table of known keys is bundled from `keys.txt`, and more keys can be
+// loaded at runtime from files in the same format.
+const BUNDLED: &str = include_str!("keys.txt");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum KeyKind {
+    Production,
+    PreProduction,
+    Oem,
+}
+
+impl Display for KeyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let k = match self {
+            Self::Production => "Intel production",
+            Self::PreProduction => "Intel pre-production/debug",
+            Self::Oem => "OEM",
+        };
+        write!(f, "{k}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KnownKey {
+    pub hash: Vec<u8>,
+    pub kind: KeyKind,
+    pub name: String,
+}
+
+impl Display for KnownKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let k = self.kind;
+        let n = &self.name;
+        write!(f, "{k} key {n}")
+    }
+}
+
+// RSA-3072 keys come with SHA-384, see `Manifest::hash_size`.
+const RSA3072_SIZE: usize = 0x180;
+
+/// Hash over the public key and exponent, as stored in the manifest;
+/// SHA-384 for RSA-3072 keys, SHA-256 otherwise.
+pub fn key_hash(key: &[u8], exponent: u32) -> Vec<u8> {
+    let e = exponent.to_le_bytes();
+    match key.len() {
+        RSA3072_SIZE => Sha384::new()
+            .chain_update(key)
+            .chain_update(e)
+            .finalize()
+            .to_vec(),
+        _ => Sha256::new()
+            .chain_update(key)
+            .chain_update(e)
+            .finalize()
+            .to_vec(),
+    }
+}
+
+/// Format a key hash the way the table lists it.
+pub fn to_hex(hash: &[u8]) -> String {
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct KeyTable(pub Vec<KnownKey>);
+
+impl KeyTable {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut keys = Vec::<KnownKey>::new();
+        for (n, l) in text.lines().enumerate() {
+            let l = l.trim();
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            let err = || format!("line {}: cannot parse {l}", n + 1);
+            let mut parts = l.splitn(3, char::is_whitespace);
+            let (Some(h), Some(k)) = (parts.next(), parts.next()) else {
+                return Err(err());
+            };
+            let name = parts.next().unwrap_or_default().trim().to_string();
+            if h.len() != 64 && h.len() != 96 {
+                return Err(err());
+            }
+            let mut hash = vec![0u8; h.len() / 2];
+            for (i, b) in hash.iter_mut().enumerate() {
+                let Ok(v) = u8::from_str_radix(&h[i * 2..i * 2 + 2], 16) else {
+                    return Err(err());
+                };
+                *b = v;
+            }
+            let kind = match k {
+                "production" => KeyKind::Production,
+                "preproduction" => KeyKind::PreProduction,
+                "oem" => KeyKind::Oem,
+                _ => return Err(err()),
+            };
+            keys.push(KnownKey { hash, kind, name });
+        }
+        Ok(Self(keys))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let p = path.display();
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {p}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("{p}: {e}"))
+    }
+
+    /// The table bundled with this crate.
+    pub fn bundled() -> &'static Self {
+        static TABLE: OnceLock<KeyTable> = OnceLock::new();
+        TABLE.get_or_init(|| Self::parse(BUNDLED).expect("bundled key table is valid"))
+    }
+
+    pub fn find(&self, hash: &[u8]) -> Option<&KnownKey> {
+        self.0.iter().find(|k| k.hash == hash)
+    }
+}
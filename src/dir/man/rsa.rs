@@ -0,0 +1,127 @@
+use num_bigint::BigUint;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384};
+
+// ASN.1 DigestInfo prefixes for EMSA-PKCS1-v1_5, see RFC 8017 section 9.2
+const SHA1_INFO: &[u8] = &[
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14,
+];
+const SHA256_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+const SHA384_INFO: &[u8] = &[
+    0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05,
+    0x00, 0x04, 0x30,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashAlgorithm {
+    Sha1,   // ME 2-5
+    Sha256, // ME 6 - CSME 11, RSA-2048
+    Sha384, // CSME 12+, RSA-3072
+}
+
+impl HashAlgorithm {
+    fn digest_info(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha1 => [SHA1_INFO, &Sha1::digest(data)].concat(),
+            Self::Sha256 => [SHA256_INFO, &Sha256::digest(data)].concat(),
+            Self::Sha384 => [SHA384_INFO, &Sha384::digest(data)].concat(),
+        }
+    }
+}
+
+/// Verify a PKCS#1 v1.5 signature over `data` with the hash algorithm `alg`.
+/// Intel stores the modulus and signature as little-endian integers.
+pub fn verify(
+    alg: HashAlgorithm,
+    modulus: &[u8],
+    exponent: u32,
+    sig: &[u8],
+    data: &[u8],
+) -> Result<bool, String> {
+    let n = BigUint::from_bytes_le(modulus);
+    let s = BigUint::from_bytes_le(sig);
+    if n.bits() == 0 {
+        return Err("empty RSA key".to_string());
+    }
+    if sig.len() != modulus.len() || s >= n {
+        return Ok(false);
+    }
+    // EM = 0x00 || 0x01 || PS (0xff, at least 8) || 0x00 || DigestInfo
+    let k = modulus.len();
+    let t = alg.digest_info(data);
+    let Some(pad) = k.checked_sub(t.len() + 3).filter(|p| *p >= 8) else {
+        return Err(format!("RSA key too short for {alg:?}"));
+    };
+    let em = [&[0x00, 0x01], &vec![0xff; pad][..], &[0x00], &t].concat();
+    let m = s.modpow(&BigUint::from(exponent), &n).to_bytes_be();
+    // leading zero bytes are dropped by to_bytes_be
+    Ok(em[k - m.len()..] == m[..] && em[..k - m.len()].iter().all(|b| *b == 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RSA-512 with exponent 17, only to test the padding checks
+    const MODULUS: [u8; 64] = [
+        0x25, 0x44, 0x14, 0x3f, 0x8b, 0xb1, 0xd2, 0x13, 0xd6, 0xae, 0x0e, 0x10, 0xc2, 0xd6, 0x78,
+        0xc3, 0x36, 0xca, 0xfd, 0x4f, 0xb3, 0x81, 0xb6, 0xdb, 0x30, 0x2f, 0x80, 0xa1, 0x3b, 0xfc,
+        0x31, 0xf2, 0xaa, 0xb0, 0x05, 0x83, 0xb7, 0x5d, 0x3c, 0x9c, 0x8a, 0x14, 0x37, 0x8b, 0x97,
+        0x0c, 0x7e, 0x20, 0x61, 0x24, 0x39, 0x6a, 0x32, 0x58, 0xe5, 0xaa, 0xca, 0x4c, 0x15, 0x2c,
+        0x76, 0xed, 0xaa, 0x66,
+    ];
+    // SHA-256 with DigestInfo over DATA
+    const SIG: [u8; 64] = [
+        0x5e, 0x16, 0x74, 0x38, 0x6f, 0x47, 0x0b, 0xa1, 0x76, 0xa4, 0xdb, 0xef, 0x93, 0x6d, 0xca,
+        0x33, 0xf1, 0xa2, 0x22, 0x3e, 0xec, 0x8a, 0xac, 0x2a, 0x1b, 0x30, 0xd7, 0x5a, 0x47, 0x94,
+        0x39, 0xe0, 0xa9, 0x81, 0x7c, 0xd5, 0xad, 0x37, 0x10, 0x90, 0x46, 0x3d, 0xa8, 0x57, 0xe8,
+        0x72, 0xf9, 0xe5, 0x8a, 0x1c, 0x40, 0x6a, 0xd6, 0x5c, 0x90, 0x37, 0xb2, 0x76, 0x4b, 0x64,
+        0x9b, 0x76, 0xde, 0x44,
+    ];
+    // SHA-256 without DigestInfo over DATA
+    const SIG_BARE: [u8; 64] = [
+        0x12, 0x93, 0x5c, 0x2b, 0xb1, 0x7a, 0x49, 0x23, 0x26, 0xbe, 0x82, 0xec, 0x92, 0xe6, 0xdf,
+        0x37, 0x6d, 0x65, 0xe2, 0x9c, 0x8c, 0xda, 0x1a, 0x9d, 0xa7, 0xe2, 0xd6, 0x2e, 0x46, 0x55,
+        0xfe, 0x36, 0x77, 0x41, 0xc4, 0xc6, 0xde, 0x0a, 0x7e, 0x1e, 0xcc, 0x29, 0x5b, 0x81, 0xe1,
+        0xca, 0x5e, 0x6f, 0x5b, 0x24, 0x7d, 0x57, 0x6f, 0x30, 0xf8, 0x46, 0x95, 0x1a, 0xc6, 0x2d,
+        0x9a, 0xc1, 0x00, 0x4b,
+    ];
+    const DATA: &[u8] = b"signed manifest data";
+
+    #[test]
+    fn valid() {
+        assert_eq!(
+            verify(HashAlgorithm::Sha256, &MODULUS, 17, &SIG, DATA),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn tampered() {
+        let mut d = DATA.to_vec();
+        d[0] ^= 1;
+        assert_eq!(
+            verify(HashAlgorithm::Sha256, &MODULUS, 17, &SIG, &d),
+            Ok(false)
+        );
+        let mut s = SIG;
+        s[0] ^= 1;
+        assert_eq!(
+            verify(HashAlgorithm::Sha256, &MODULUS, 17, &s, DATA),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let alg = HashAlgorithm::Sha256;
+        assert_eq!(verify(alg, &MODULUS, 17, &SIG_BARE, DATA), Ok(false));
+        assert_eq!(
+            verify(HashAlgorithm::Sha1, &MODULUS, 17, &SIG, DATA),
+            Ok(false)
+        );
+    }
+}
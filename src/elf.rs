@@ -0,0 +1,264 @@
+use zerocopy::IntoBytes;
+use zerocopy_derive::{Immutable, IntoBytes};
+
+// Minimal ELF32 little-endian executables, so that disassemblers load
+// modules at their runtime addresses.
+// see https://refspecs.linuxfoundation.org/elf/elf.pdf
+pub const EM_386: u16 = 3; // CSME 11+
+pub const EM_ARC_COMPACT: u16 = 93; // ME 6-10
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+const ET_EXEC: u16 = 2;
+const EV_CURRENT: u32 = 1;
+const PT_LOAD: u32 = 1;
+const SHT_PROGBITS: u32 = 1;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const PAGE_SIZE: u32 = 0x1000;
+
+#[derive(Immutable, IntoBytes, Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct Header {
+    ident: [u8; 16],
+    file_type: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    ph_offset: u32,
+    sh_offset: u32,
+    flags: u32,
+    header_size: u16,
+    ph_entry_size: u16,
+    ph_count: u16,
+    sh_entry_size: u16,
+    sh_count: u16,
+    sh_str_index: u16,
+}
+
+#[derive(Immutable, IntoBytes, Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct ProgramHeader {
+    p_type: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    file_size: u32,
+    mem_size: u32,
+    flags: u32,
+    align: u32,
+}
+
+#[derive(Immutable, IntoBytes, Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct SectionHeader {
+    name: u32, // offset in the section name string table
+    sh_type: u32,
+    flags: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entry_size: u32,
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<Header>();
+const PH_SIZE: usize = core::mem::size_of::<ProgramHeader>();
+const SH_SIZE: usize = core::mem::size_of::<SectionHeader>();
+
+/// A loadable segment; memory beyond the data is zero filled, e.g., BSS.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub name: String,
+    pub addr: u32,
+    pub data: Vec<u8>,
+    pub mem_size: u32,
+    pub flags: u32, // PF_*
+}
+
+impl Segment {
+    /// Cut the range `start..end` out of `image`, which is mapped at `base`.
+    pub fn from_image(
+        name: &str,
+        image: &[u8],
+        base: u32,
+        start: u32,
+        end: u32,
+        flags: u32,
+    ) -> Self {
+        let end = end.max(start);
+        let o = start.saturating_sub(base) as usize;
+        let l = (end - start) as usize;
+        let data = match image.get(o..) {
+            Some(d) => d[..l.min(d.len())].to_vec(),
+            None => Vec::new(),
+        };
+        Self {
+            name: name.to_string(),
+            addr: start,
+            data,
+            mem_size: end - start,
+            flags,
+        }
+    }
+}
+
+/// Write an ELF32 executable with a PT_LOAD program header per segment, and
+/// sections for the data and the zero filled memory of each.
+pub fn write(machine: u16, entry: u32, segments: &[Segment]) -> Vec<u8> {
+    let ph_count = segments.len();
+    let mut out = vec![0u8; HEADER_SIZE + ph_count * PH_SIZE];
+    let mut strtab = vec![0u8];
+    let mut add_name = |n: &str| {
+        let o = strtab.len() as u32;
+        strtab.extend_from_slice(n.as_bytes());
+        strtab.push(0);
+        o
+    };
+    let mut phs = Vec::<ProgramHeader>::with_capacity(ph_count);
+    let mut shs = vec![SectionHeader::default()];
+    for s in segments {
+        // file offset and address have to be congruent modulo the alignment
+        let pad = (s.addr as usize).wrapping_sub(out.len()) % PAGE_SIZE as usize;
+        out.resize(out.len() + pad, 0);
+        let offset = out.len() as u32;
+        out.extend_from_slice(&s.data);
+        let file_size = s.data.len() as u32;
+        let mem_size = s.mem_size.max(file_size);
+        phs.push(ProgramHeader {
+            p_type: PT_LOAD,
+            offset,
+            vaddr: s.addr,
+            paddr: s.addr,
+            file_size,
+            mem_size,
+            flags: s.flags,
+            align: PAGE_SIZE,
+        });
+        let mut flags = SHF_ALLOC;
+        if s.flags & PF_W != 0 {
+            flags |= SHF_WRITE;
+        }
+        if s.flags & PF_X != 0 {
+            flags |= SHF_EXECINSTR;
+        }
+        if file_size > 0 {
+            shs.push(SectionHeader {
+                name: add_name(&s.name),
+                sh_type: SHT_PROGBITS,
+                flags,
+                addr: s.addr,
+                offset,
+                size: file_size,
+                align: 4,
+                ..Default::default()
+            });
+        }
+        if mem_size > file_size {
+            shs.push(SectionHeader {
+                name: add_name(".bss"),
+                sh_type: SHT_NOBITS,
+                flags,
+                addr: s.addr.wrapping_add(file_size),
+                offset: offset + file_size,
+                size: mem_size - file_size,
+                align: 4,
+                ..Default::default()
+            });
+        }
+    }
+    let name = add_name(".shstrtab");
+    shs.push(SectionHeader {
+        name,
+        sh_type: SHT_STRTAB,
+        offset: out.len() as u32,
+        size: strtab.len() as u32,
+        align: 1,
+        ..Default::default()
+    });
+    out.extend_from_slice(&strtab);
+    out.resize(out.len().next_multiple_of(4), 0);
+
+    let sh_offset = out.len() as u32;
+    for sh in &shs {
+        out.extend_from_slice(sh.as_bytes());
+    }
+    let mut ident = [0u8; 16];
+    // magic, 32 bit, little endian, version 1
+    ident[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1, 1]);
+    let header = Header {
+        ident,
+        file_type: ET_EXEC,
+        machine,
+        version: EV_CURRENT,
+        entry,
+        ph_offset: HEADER_SIZE as u32,
+        sh_offset,
+        flags: 0,
+        header_size: HEADER_SIZE as u16,
+        ph_entry_size: PH_SIZE as u16,
+        ph_count: ph_count as u16,
+        sh_entry_size: SH_SIZE as u16,
+        sh_count: shs.len() as u16,
+        sh_str_index: (shs.len() - 1) as u16,
+    };
+    out[..HEADER_SIZE].copy_from_slice(header.as_bytes());
+    for (i, ph) in phs.iter().enumerate() {
+        let o = HEADER_SIZE + i * PH_SIZE;
+        out[o..o + PH_SIZE].copy_from_slice(ph.as_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(d: &[u8], o: usize) -> u32 {
+        u32::from_le_bytes(d[o..o + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn load_addresses() {
+        let segments = [
+            Segment::from_image(
+                "text",
+                &[0x90; 0x123],
+                0x2000_1000,
+                0x2000_1000,
+                0x2000_1123,
+                PF_R | PF_X,
+            ),
+            Segment {
+                name: "data".to_string(),
+                addr: 0x2000_5010,
+                data: vec![1, 2, 3, 4],
+                mem_size: 0x100,
+                flags: PF_R | PF_W,
+            },
+        ];
+        let e = write(EM_386, 0x2000_1040, &segments);
+        assert_eq!(&e[..4], b"\x7fELF");
+        assert_eq!(u32_at(&e, 0x18), 0x2000_1040); // e_entry
+        let ph_offset = u32_at(&e, 0x1c) as usize;
+        assert_eq!(u16::from_le_bytes([e[0x2c], e[0x2d]]), 2); // e_phnum
+        for (i, s) in segments.iter().enumerate() {
+            let ph = ph_offset + i * PH_SIZE;
+            assert_eq!(u32_at(&e, ph), PT_LOAD);
+            let offset = u32_at(&e, ph + 4);
+            let vaddr = u32_at(&e, ph + 8);
+            assert_eq!(vaddr, s.addr);
+            assert_eq!(offset % PAGE_SIZE, vaddr % PAGE_SIZE);
+            let o = offset as usize;
+            assert_eq!(&e[o..o + s.data.len()], &s.data[..]);
+            assert_eq!(u32_at(&e, ph + 20), s.mem_size); // p_memsz
+        }
+    }
+}
@@ -128,7 +128,7 @@ impl Fit {
         }
         let offset = mapping & fp as usize;
         // NOTE: FIT is usually aligned. The spec does not mandate it though.
-        if offset % 0x10 != 0 {
+        if !offset.is_multiple_of(0x10) {
             let err = format!("Not a FIT pointer: {offset:08x}");
             return Err(err);
         }
@@ -136,6 +136,9 @@ impl Fit {
         let Ok((header, _)) = FitHeader::read_from_prefix(&data[offset..]) else {
             return Err(format!("No FIT header @ {offset:08x}"));
         };
+        if header.magic != *FIT_MAGIC.as_bytes() {
+            return Err(format!("FIT magic not found @ {offset:08x}"));
+        }
         // NOTE: The header counts as a first entry.
         let count = (header.entries - 1) as usize;
         let pos = offset + FIT_HEADER_SIZE;
@@ -2,9 +2,12 @@ use core::fmt::{self, Display};
 use serde::{Deserialize, Serialize};
 use zerocopy_derive::{FromBytes, IntoBytes};
 
+use crate::afsp::AFSP;
+use crate::dir::gen1::Directory as Gen1Directory;
 use crate::dir::gen2::Directory as Gen2Directory;
 use crate::dir::gen3::CodePartitionDirectory;
 use crate::fit::Fit;
+use crate::mfs::{check::Report, MFS};
 use crate::ver::Version;
 
 // see https://github.com/peterbjornx/meimagetool ...intelme/model/fpt/ (Java)
@@ -80,7 +83,11 @@ pub struct ME_FPT {
     pub entries: Vec<FPTEntry>,
     pub gen3dirs: Vec<CodePartitionDirectory>,
     pub gen2dirs: Vec<Gen2Directory>,
+    pub gen1dirs: Vec<Gen1Directory>,
     pub fit: Result<Fit, String>,
+    pub mfs: Result<MFS, String>,
+    pub mfs_check: Option<Report>,
+    pub afsp: Result<AFSP, String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -117,7 +124,7 @@ pub fn get_part_info(n: &str) -> (PartitionType, &str) {
         "FLOG" => (PartitionType::Data, "Flash Log"),
         "UTOK" => (PartitionType::Data, "Debug Unlock Token"),
         "ISHC" => (PartitionType::Code, "Integrated Sensors Hub"),
-        "AFSP" => (PartitionType::None, "8778 55aa signature like MFS"),
+        "AFSP" => (PartitionType::Data, "MFS-like, 8778 55aa signature"),
         "FTPM" => (PartitionType::Code, "Firmware TPM (unconfirmed)"),
         "GLUT" => (PartitionType::Data, "Huffman Look-Up Table"),
         "EFFS" => (PartitionType::Data, "EFFS File System"),
@@ -1,9 +1,12 @@
 use std::mem;
 use zerocopy::FromBytes;
 
+pub mod afsp;
 pub mod dir;
+pub mod elf;
 pub mod fit;
 pub mod fpt;
+pub mod mfs;
 pub mod ver;
 
 pub use fpt::ME_FPT;
@@ -25,7 +28,11 @@ pub fn parse(data: &[u8], debug: bool) -> Result<ME_FPT, String> {
     let cpd_bytes = dir::gen3::CPD_MAGIC.as_bytes();
     let mut entries = Vec::<fpt::FPTEntry>::new();
     let mut gen2dirs = Vec::<dir::gen2::Directory>::new();
+    let mut gen1dirs = Vec::<dir::gen1::Directory>::new();
     let mut gen3dirs = Vec::<dir::gen3::CodePartitionDirectory>::new();
+    let mut mfs = Err("no MFS partition found".to_string());
+    let mut mfs_check = None;
+    let mut afsp = Err("no AFSP partition found".to_string());
 
     // Scan for all CPDs (there may be some not listed in FPT)
     if debug {
@@ -33,10 +40,9 @@ pub fn parse(data: &[u8], debug: bool) -> Result<ME_FPT, String> {
         while o < data.len() {
             let buf = &data[o..o + 4];
             if buf.eq(cpd_bytes) {
-                let Ok(cpd) = dir::gen3::CodePartitionDirectory::new(data[o..].to_vec(), o) else {
-                    continue;
-                };
-                gen3dirs.push(cpd);
+                if let Ok(cpd) = dir::gen3::CodePartitionDirectory::new(data[o..].to_vec(), o) {
+                    gen3dirs.push(cpd);
+                }
             }
             o += 16;
         }
@@ -87,6 +93,8 @@ pub fn parse(data: &[u8], debug: bool) -> Result<ME_FPT, String> {
                                 }
                             } else if let Ok(dir) = dir::gen2::Directory::new(&data[o..], o) {
                                 gen2dirs.push(dir);
+                            } else if let Ok(dir) = dir::gen1::Directory::new(&data[o..], o) {
+                                gen1dirs.push(dir);
                             } else if debug {
                                 println!("{name} @ {o:08x} has no CPD signature");
                                 dump48(&data[o..]);
@@ -94,7 +102,14 @@ pub fn parse(data: &[u8], debug: bool) -> Result<ME_FPT, String> {
                         }
                     }
                     MFS | AFSP | EFFS => {
-                        // TODO: parse MFS
+                        if n == MFS && o + s <= data.len() {
+                            let d = &data[o..o + s];
+                            mfs = mfs::MFS::new(d, o);
+                            mfs_check = Some(mfs::check::check(d, o));
+                        }
+                        if n == AFSP && o + s <= data.len() {
+                            afsp = afsp::AFSP::new(&data[o..o + s], o);
+                        }
                     }
                     _ => {
                         if !debug {
@@ -128,7 +143,11 @@ pub fn parse(data: &[u8], debug: bool) -> Result<ME_FPT, String> {
                 entries,
                 gen3dirs,
                 gen2dirs,
+                gen1dirs,
                 fit,
+                mfs,
+                mfs_check,
+                afsp,
             };
             return Ok(me_fpt);
         }
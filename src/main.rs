@@ -1,16 +1,38 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use me_fs_rs::afsp::AFSP;
 use me_fs_rs::fit::Fit;
+use me_fs_rs::mfs::{
+    cfg::Config,
+    check::Report,
+    tree::{Node, FITC_CFG, INTEL_CFG},
+    MFS,
+};
 use me_fs_rs::{
-    dir::gen2::Directory as Gen2Dir, dir::gen3::CodePartitionDirectory, fpt::FPTEntry, parse,
-    ME_FPT,
+    dir::gen1::Directory as Gen1Dir,
+    dir::gen2::{Compression, Directory as Gen2Dir, SIG_LUT, SIG_LZMA},
+    dir::gen3::CodePartitionDirectory,
+    dir::huffman::Dictionaries,
+    dir::man::{
+        ext::Extension,
+        keys::{self, KeyTable},
+        Manifest,
+    },
+    fpt::FPTEntry,
+    fpt::MFS as MFS_NAME,
+    parse, ME_FPT,
 };
 use std::fs;
-use std::io;
+use std::io::{self, Write};
+use std::path::Path;
 
 /// Print Intel (CS)ME FPT information
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    cmd: Option<Command>,
+
     /// Print header data
     #[arg(required = false, short, long)]
     print: bool,
@@ -24,54 +46,214 @@ struct Args {
     debug: bool,
 
     /// File to read
-    #[arg(index = 1)]
-    file: String,
+    #[arg(index = 1, required = true)]
+    file: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect and modify the ME file system (MFS)
+    #[command(subcommand)]
+    Mfs(MfsCommand),
+    /// Write all modules, decompressed, to a directory
+    Extract {
+        /// File to read
+        file: String,
+        /// Directory to write to, one subdirectory per partition
+        dir: String,
+        /// Directory with Huffman dictionaries, <chipset>-<n>.dict
+        #[arg(long)]
+        dicts: Option<String>,
+    },
+    /// Write all modules of ME 6+ as ELF files mapped at their runtime addresses
+    Export {
+        /// File to read
+        file: String,
+        /// Directory to write to, one subdirectory per partition
+        dir: String,
+        /// Directory with Huffman dictionaries, <chipset>-<n>.dict
+        #[arg(long)]
+        dicts: Option<String>,
+    },
+    /// Verify manifest signatures and module hashes
+    Verify {
+        /// File to read
+        file: String,
+        /// Directory with Huffman dictionaries, <chipset>-<n>.dict
+        #[arg(long)]
+        dicts: Option<String>,
+        /// File with more known key hashes, in the format of keys.txt
+        #[arg(long)]
+        keys: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MfsCommand {
+    /// List a directory (CSME 11+)
+    Ls {
+        /// File to read
+        file: String,
+        /// Path within the MFS
+        #[arg(default_value = "/")]
+        path: String,
+    },
+    /// Write a file's contents to stdout (CSME 11+)
+    Cat {
+        /// File to read
+        file: String,
+        /// Path within the MFS
+        path: String,
+    },
+    /// Print the records of intel.cfg and fitc.cfg
+    Cfg {
+        /// File to read
+        file: String,
+    },
+    /// Check pages, chunks and the file allocation table for consistency
+    Check {
+        /// File to read
+        file: String,
+    },
+    /// Delete a file by its index and write the modified image
+    Rm {
+        /// File to read
+        file: String,
+        /// MFS file index
+        index: usize,
+        /// File to write
+        out: String,
+    },
+    /// Reset to factory state, keeping only intel.cfg and fitc.cfg
+    Reset {
+        /// File to read
+        file: String,
+        /// File to write
+        out: String,
+    },
+}
+
+fn signature_status(sig: &Result<bool, String>) -> String {
+    match sig {
+        Ok(true) => "signature valid".to_string(),
+        Ok(false) => "signature INVALID".to_string(),
+        Err(e) => format!("signature not checked: {e}"),
+    }
 }
 
-fn print_gen2_dirs(dirs: &Vec<Gen2Dir>) {
+fn print_gen1_dirs(dirs: &Vec<Gen1Dir>) {
+    println!("Gen 1 Directories:");
+    for dir in dirs {
+        println!("{dir}");
+        println!("  {}", signature_status(&dir.signature));
+        for e in &dir.entries {
+            println!(" - {e}");
+        }
+        println!();
+    }
+}
+
+fn print_gen2_dirs(dirs: &Vec<Gen2Dir>, data: &[u8]) {
     println!("Gen 2 Directories:");
     for dir in dirs {
         println!("{dir}");
+        println!("  {}", signature_status(&dir.signature));
+        for t in &dir.trailers {
+            println!("  {t}");
+        }
+        if let Err(e) = &dir.lut_offset {
+            println!("  {e}");
+        }
         for e in &dir.entries {
-            let pos = dir.offset + e.offset as usize;
-            /*
-            let sig =
-                u32::read_from_prefix(&data[pos..pos + 4]).unwrap();
+            let pos = match (e.compression_type(), &dir.lut_offset) {
+                (Compression::Huffman, Ok(Some(l))) => dir.offset + l,
+                _ => dir.offset + e.offset as usize,
+            };
+            let sig = data
+                .get(pos..pos + 4)
+                .map(|s| u32::from_le_bytes(s.try_into().unwrap()));
             let kind = match sig {
-                SIG_LUT => "LLUT",
-                SIG_LZMA => "LZMA",
-                _ => {
-                    dump48(&data[pos..]);
-                    "unknown"
-                }
+                Some(SIG_LUT) => "LLUT",
+                Some(SIG_LZMA) => "LZMA",
+                _ => "raw",
             };
-            */
-            let kind = "...";
             let t = e.compression_type();
             let b = e.bin_map();
+            let f = e.module_flags();
+            let l = e.load_attributes();
             println!(" - {e}    {pos:08x} {t:?} ({kind})\n     {b}");
+            println!("     {f}");
+            println!("     {l}");
         }
         println!();
     }
 }
 
+fn print_extensions(exts: &Result<Vec<Extension>, String>) {
+    match exts {
+        Ok(exts) => {
+            for e in exts {
+                println!("    {e}");
+            }
+        }
+        Err(e) => println!("    {e}"),
+    }
+}
+
 fn print_gen3_dirs(dirs: &Vec<CodePartitionDirectory>) {
     println!("Gen 3 Directories:");
     for d in dirs {
         println!();
-        let checksum = d.header.version_or_checksum;
         let o = d.offset;
-        println!("{} @ {o:08x}, checksum or version: {checksum:08x}", d.name);
+        let h = d.header;
+        println!("{} @ {o:08x}, {h}", d.name);
+        if let Err(e) = &d.checksum {
+            println!("  CORRUPTED: {e}");
+        }
         match &d.manifest {
             Ok(m) => println!("{m}"),
             Err(e) => println!("{e}"),
         }
+        for m in &d.manifests {
+            let f = m.files.join(", ");
+            let sig = signature_status(&m.signature);
+            match &m.manifest {
+                Ok(man) => {
+                    let k = man.kind;
+                    let d = if man.header.is_debug() {
+                        "debug"
+                    } else {
+                        "production"
+                    };
+                    let p = if man.header.is_pre_production() {
+                        ", pre-production"
+                    } else {
+                        ""
+                    };
+                    let n = &m.name;
+                    if m.files.is_empty() {
+                        println!("  {n} ({k}, {d} signed{p}), {sig}");
+                    } else {
+                        println!("  {n} ({k}, {d} signed{p}), {sig}, signs: {f}");
+                    }
+                }
+                Err(e) => println!("  {}: {e}", m.name),
+            }
+            print_extensions(&m.extensions);
+        }
+        for m in &d.metadata {
+            println!("  {}", m.name);
+            print_extensions(&m.extensions);
+        }
 
-        println!("  file name        offset    end       size           compression flags");
+        println!("  file name        offset    end       size       attributes   compression");
         let mut entries = d.entries.clone();
-        entries.sort_by_key(|e| e.offset);
+        entries.sort_by_key(|e| e.offset());
         for e in entries {
-            println!("  {e}");
+            match d.compression(&e) {
+                Ok(c) => println!("  {e} {c:?}"),
+                Err(err) => println!("  {e} {err}"),
+            }
         }
     }
 }
@@ -98,9 +280,322 @@ fn print_fit(fit: &Result<Fit, String>) {
     }
 }
 
+fn print_mfs(mfs: &Result<MFS, String>, debug: bool) {
+    match mfs {
+        Ok(mfs) => {
+            println!("{mfs}");
+            if debug {
+                println!("  page kind   details");
+                for p in &mfs.pages {
+                    println!("  {p}");
+                }
+            }
+            println!("  file   size");
+            for f in &mfs.files {
+                println!("  {f}");
+            }
+        }
+        Err(e) => {
+            println!("Could not parse MFS: {e}");
+        }
+    }
+}
+
+fn print_afsp(afsp: &Result<AFSP, String>) {
+    match afsp {
+        Ok(afsp) => {
+            println!("{afsp}");
+            for d in &afsp.differences {
+                println!("  differs: {d}");
+            }
+            print_mfs_check(&Some(afsp.check.clone()));
+            println!("  file   size");
+            for f in &afsp.volume.files {
+                println!("  {f}");
+            }
+        }
+        Err(e) => {
+            println!("Could not parse AFSP: {e}");
+        }
+    }
+}
+
+fn extract(file: &str, dir: &str, dicts: Option<String>) -> Result<(), String> {
+    let data = fs::read(file).map_err(|e| format!("cannot read {file}: {e}"))?;
+    let fpt = parse(&data, false)?;
+    let dicts = match dicts {
+        Some(d) => Dictionaries::load(Path::new(&d))?,
+        None => Dictionaries::default(),
+    };
+    for d in &fpt.gen1dirs {
+        let p = Path::new(dir).join(&d.name);
+        fs::create_dir_all(&p).map_err(|e| format!("cannot create {}: {e}", p.display()))?;
+        for e in &d.entries {
+            let n = e.name();
+            match d.module(&data, e) {
+                Ok(m) => {
+                    let f = p.join(format!("{n}.bin"));
+                    fs::write(&f, m).map_err(|e| format!("cannot write {}: {e}", f.display()))?;
+                    println!("{}", f.display());
+                }
+                Err(err) => eprintln!("Skipping {}/{n}: {err}", d.name),
+            }
+        }
+    }
+    for d in &fpt.gen2dirs {
+        let p = Path::new(dir).join(&d.name);
+        fs::create_dir_all(&p).map_err(|e| format!("cannot create {}: {e}", p.display()))?;
+        for e in &d.entries {
+            let n = e.name();
+            match d.module(&data, e, &dicts) {
+                Ok(m) => {
+                    let f = p.join(format!("{n}.bin"));
+                    fs::write(&f, m).map_err(|e| format!("cannot write {}: {e}", f.display()))?;
+                    println!("{}", f.display());
+                }
+                Err(err) => eprintln!("Skipping {}/{n}: {err}", d.name),
+            }
+        }
+    }
+    for d in &fpt.gen3dirs {
+        if let Err(e) = &d.checksum {
+            eprintln!("Warning: {} @ {:08x} is corrupted: {e}", d.name, d.offset);
+        }
+        let p = Path::new(dir).join(&d.name);
+        fs::create_dir_all(&p).map_err(|e| format!("cannot create {}: {e}", p.display()))?;
+        for e in &d.entries {
+            let n = e.name();
+            match d.file(&data, e, &dicts) {
+                Ok(m) => {
+                    let f = p.join(&n);
+                    fs::write(&f, m).map_err(|e| format!("cannot write {}: {e}", f.display()))?;
+                    println!("{}", f.display());
+                }
+                Err(err) => eprintln!("Skipping {}/{n}: {err}", d.name),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn export(file: &str, dir: &str, dicts: Option<String>) -> Result<(), String> {
+    let data = fs::read(file).map_err(|e| format!("cannot read {file}: {e}"))?;
+    let fpt = parse(&data, false)?;
+    let dicts = match dicts {
+        Some(d) => Dictionaries::load(Path::new(&d))?,
+        None => Dictionaries::default(),
+    };
+    let write = |p: &Path, n: &str, elf: Vec<u8>| {
+        let f = p.join(format!("{n}.elf"));
+        fs::write(&f, elf).map_err(|e| format!("cannot write {}: {e}", f.display()))?;
+        println!("{}", f.display());
+        Ok::<(), String>(())
+    };
+    for d in &fpt.gen2dirs {
+        let p = Path::new(dir).join(&d.name);
+        fs::create_dir_all(&p).map_err(|e| format!("cannot create {}: {e}", p.display()))?;
+        for e in &d.entries {
+            let n = e.name();
+            match d.elf(&data, e, &dicts) {
+                Ok(elf) => write(&p, &n, elf)?,
+                Err(err) => eprintln!("Skipping {}/{n}: {err}", d.name),
+            }
+        }
+    }
+    for d in &fpt.gen3dirs {
+        let p = Path::new(dir).join(&d.name);
+        fs::create_dir_all(&p).map_err(|e| format!("cannot create {}: {e}", p.display()))?;
+        for m in &d.metadata {
+            let n = m.name.trim_end_matches(".met");
+            match d.elf(&data, m, &dicts) {
+                Ok(elf) => write(&p, n, elf)?,
+                Err(err) => eprintln!("Skipping {}/{n}: {err}", d.name),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn key_status(m: &Result<Manifest, String>, keys: &KeyTable) -> String {
+    let Ok(m) = m else {
+        return "no manifest".to_string();
+    };
+    let h = m.key_hash();
+    let kh = keys::to_hex(&h);
+    match keys.find(&h) {
+        Some(k) => format!("{k} ({kh})"),
+        None => format!("unknown key {kh}"),
+    }
+}
+
+fn print_hashes(hashes: Vec<(String, Result<bool, String>)>) {
+    for (n, r) in hashes {
+        match r {
+            Ok(true) => println!("  {n:12} hash OK"),
+            Ok(false) => println!("  {n:12} hash MISMATCH"),
+            Err(e) => println!("  {n:12} not checked: {e}"),
+        }
+    }
+}
+
+fn verify(file: &str, dicts: Option<String>, keys: Option<String>) -> Result<(), String> {
+    let data = fs::read(file).map_err(|e| format!("cannot read {file}: {e}"))?;
+    let fpt = parse(&data, false)?;
+    let dicts = match dicts {
+        Some(d) => Dictionaries::load(Path::new(&d))?,
+        None => Dictionaries::default(),
+    };
+    let mut table = KeyTable::bundled().clone();
+    if let Some(k) = keys {
+        table.0.extend(KeyTable::load(Path::new(&k))?.0);
+    }
+    if table.0.is_empty() {
+        println!("No known keys, pass them with --keys to identify signing keys");
+    }
+    for d in &fpt.gen1dirs {
+        let sig = signature_status(&d.signature);
+        println!("{} @ {:08x}, {sig}", d.name, d.offset);
+        let m = Ok(d.manifest.clone());
+        println!("  {}", key_status(&m, &table));
+        print_hashes(d.verify_hashes(&data));
+    }
+    for d in &fpt.gen2dirs {
+        let sig = signature_status(&d.signature);
+        println!("{} @ {:08x}, {sig}", d.name, d.offset);
+        let m = Ok(d.manifest.clone());
+        println!("  {}", key_status(&m, &table));
+        print_hashes(d.verify_hashes(&data, &dicts));
+    }
+    for d in &fpt.gen3dirs {
+        let sig = signature_status(&d.signature);
+        println!("{} @ {:08x}, {sig}", d.name, d.offset);
+        for m in &d.manifests {
+            println!("  {:12} {}", m.name, key_status(&m.manifest, &table));
+        }
+        print_hashes(d.verify_hashes(&data, &dicts));
+    }
+    Ok(())
+}
+
+fn print_mfs_check(report: &Option<Report>) {
+    match report {
+        Some(r) if r.is_ok() => println!("{r}, all good"),
+        Some(r) => println!("{r}"),
+        None => println!("No MFS to check"),
+    }
+}
+
+fn print_cfg(mfs: &MFS) {
+    for (name, index) in [("intel.cfg", INTEL_CFG), ("fitc.cfg", FITC_CFG)] {
+        println!("{name}:");
+        match Config::from_mfs(mfs, index) {
+            Ok(c) => {
+                println!("  mode           opt    uid   gid   offset     size     path");
+                for e in &c.entries {
+                    // small values are the interesting settings
+                    if !e.mode().is_dir() && e.data.len() <= 0x10 {
+                        println!("  {e} = {:02x?}", e.data);
+                    } else {
+                        println!("  {e}");
+                    }
+                }
+            }
+            Err(e) => println!("  {e}"),
+        }
+        println!();
+    }
+}
+
+fn mfs_ls(mfs: &MFS, path: &str) -> Result<(), String> {
+    let root = Node::root(mfs)?;
+    let Some(node) = root.find(path) else {
+        return Err(format!("{path} not found"));
+    };
+    if node.mode.is_dir() {
+        for c in &node.children {
+            println!("{c}");
+        }
+    } else {
+        println!("{node}");
+    }
+    Ok(())
+}
+
+fn mfs_cat(mfs: &MFS, path: &str) -> Result<(), String> {
+    let root = Node::root(mfs)?;
+    let Some(node) = root.find(path) else {
+        return Err(format!("{path} not found"));
+    };
+    if node.mode.is_dir() {
+        return Err(format!("{path} is a directory"));
+    }
+    io::stdout()
+        .write_all(&node.data)
+        .map_err(|e| format!("cannot write {path}: {e}"))
+}
+
+fn mfs_write(data: &mut [u8], entries: &[FPTEntry], mfs: &MFS, out: &str) -> Result<(), String> {
+    let Some(e) = entries
+        .iter()
+        .find(|e| u32::from_be_bytes(e.name) == MFS_NAME)
+    else {
+        return Err("no MFS entry in FPT".to_string());
+    };
+    mfs.write_into(data, e)?;
+    fs::write(out, data).map_err(|e| format!("cannot write {out}: {e}"))
+}
+
+fn run_mfs(cmd: MfsCommand) -> Result<(), String> {
+    let file = match &cmd {
+        MfsCommand::Ls { file, .. }
+        | MfsCommand::Cat { file, .. }
+        | MfsCommand::Cfg { file }
+        | MfsCommand::Check { file }
+        | MfsCommand::Rm { file, .. }
+        | MfsCommand::Reset { file, .. } => file,
+    };
+    let mut data = fs::read(file).map_err(|e| format!("cannot read {file}: {e}"))?;
+    let fpt = parse(&data, false)?;
+    match cmd {
+        MfsCommand::Ls { path, .. } => mfs_ls(&fpt.mfs?, &path),
+        MfsCommand::Cat { path, .. } => mfs_cat(&fpt.mfs?, &path),
+        MfsCommand::Cfg { .. } => {
+            print_cfg(&fpt.mfs?);
+            Ok(())
+        }
+        MfsCommand::Check { .. } => {
+            print_mfs_check(&fpt.mfs_check);
+            Ok(())
+        }
+        MfsCommand::Rm { index, out, .. } => {
+            let mut mfs = fpt.mfs?;
+            mfs.remove_file(index)?;
+            mfs_write(&mut data, &fpt.entries, &mfs, &out)
+        }
+        MfsCommand::Reset { out, .. } => {
+            let mut mfs = fpt.mfs?;
+            mfs.reset()?;
+            mfs_write(&mut data, &fpt.entries, &mfs, &out)
+        }
+    }
+}
+
 fn main() -> io::Result<()> {
     let args = Args::parse();
-    let file = args.file;
+    if let Some(cmd) = args.cmd {
+        let res = match cmd {
+            Command::Mfs(cmd) => run_mfs(cmd),
+            Command::Extract { file, dir, dicts } => extract(&file, &dir, dicts),
+            Command::Export { file, dir, dicts } => export(&file, &dir, dicts),
+            Command::Verify { file, dicts, keys } => verify(&file, dicts, keys),
+        };
+        if let Err(e) = res {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let file = args.file.unwrap();
     println!("Scanning {file} for ME FPT");
 
     let data = fs::read(file).unwrap();
@@ -114,7 +609,11 @@ fn main() -> io::Result<()> {
                 entries,
                 gen3dirs,
                 gen2dirs,
+                gen1dirs,
                 fit,
+                mfs,
+                mfs_check,
+                afsp,
             } = fpt;
             if args.print || args.verbose || args.debug {
                 println!("FPT at 0x{base:08x}: {header}");
@@ -125,12 +624,20 @@ fn main() -> io::Result<()> {
             }
             if args.verbose || args.debug {
                 println!();
+                if !gen1dirs.is_empty() {
+                    print_gen1_dirs(&gen1dirs);
+                }
                 if !gen2dirs.is_empty() {
-                    print_gen2_dirs(&gen2dirs);
+                    print_gen2_dirs(&gen2dirs, &data);
                 }
                 if !gen3dirs.is_empty() {
                     print_gen3_dirs(&gen3dirs);
                 }
+                println!();
+                print_mfs(&mfs, args.debug);
+                print_mfs_check(&mfs_check);
+                println!();
+                print_afsp(&afsp);
             }
         }
         Err(e) => {
@@ -0,0 +1,517 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zerocopy::{FromBytes, IntoBytes, Ref};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
+
+pub mod cfg;
+pub mod check;
+pub mod tree;
+pub mod write;
+
+// see https://github.com/ptresearch/parseMFS
+// and Dmitry Sklyarov, "Intel ME: Flash File System Explained", Black Hat EU 2017
+pub const PAGE_MAGIC: u32 = 0xaa55_7887;
+pub const VOLUME_MAGIC: u32 = 0x724f_6201;
+pub const VOLUME_VERSION: u32 = 1;
+
+pub const PAGE_SIZE: usize = 0x2000;
+pub const CHUNK_SIZE: usize = 0x40;
+const CHUNK_CRC_SIZE: usize = 2;
+pub(crate) const RAW_CHUNK_SIZE: usize = CHUNK_SIZE + CHUNK_CRC_SIZE;
+
+// NOTE: One in twelve pages holds the system area, one page is kept erased
+// as a spare for wear leveling, and the rest holds file data.
+const SYS_PAGE_RATIO: usize = 12;
+pub const SYS_PAGE_CHUNKS: usize = 120;
+pub const DATA_PAGE_CHUNKS: usize = 122;
+
+pub(crate) const FREE_INDEX: u16 = 0xffff;
+pub(crate) const UNUSED_INDEX: u16 = 0x7fff;
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct PageHeader {
+    pub magic: u32,
+    pub usn: u32, // update sequence number, the highest one wins
+    pub erase_count: u32,
+    pub next_erase: u16,  // index of the page to be erased next
+    pub first_chunk: u16, // 0 for system pages
+    pub crc8: u8,
+    pub _11: u8,
+}
+
+pub const PAGE_HEADER_SIZE: usize = core::mem::size_of::<PageHeader>();
+// system pages: header, chunk indices plus terminator, chunks
+pub(crate) const SYS_CHUNKS_OFFSET: usize = PAGE_HEADER_SIZE + (SYS_PAGE_CHUNKS + 1) * 2;
+// data pages: header, one free flag per chunk, chunks
+pub(crate) const DATA_CHUNKS_OFFSET: usize = PAGE_HEADER_SIZE + DATA_PAGE_CHUNKS;
+
+// CRC-8 with polynomial 0x07 over the first 16 header bytes
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |c, b| {
+        (0..8).fold(c ^ b, |c, _| {
+            if c & 0x80 != 0 {
+                (c << 1) ^ 0x07
+            } else {
+                c << 1
+            }
+        })
+    })
+}
+
+// CRC-16/CCITT over chunk data and chunk index
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |c, &b| {
+        (0..8).fold(c ^ ((b as u16) << 8), |c, _| {
+            if c & 0x8000 != 0 {
+                (c << 1) ^ 0x1021
+            } else {
+                c << 1
+            }
+        })
+    })
+}
+
+// CRC-16/CCITT truncated to 14 bits, seeded with 0x3fff, over the index of
+// the previous chunk; system chunk indices are stored XORed with it.
+// see parseMFS, MFS_Page
+fn crc_index(prev: u16) -> u16 {
+    prev.to_le_bytes().iter().fold(0x3fff, |c, &b| {
+        (0..8).fold(c ^ ((b as u16) << 8), |c, _| {
+            if c & 0x8000 != 0 {
+                (c << 1) ^ 0x1021
+            } else {
+                c << 1
+            }
+        }) & 0x3fff
+    })
+}
+
+/// Decode the stored system chunk indices, stopping at the first free or
+/// unused slot.
+pub fn decode_indices(stored: &[u16]) -> Vec<u16> {
+    let mut prev = 0;
+    stored
+        .iter()
+        .take_while(|&&x| x != FREE_INDEX && x != UNUSED_INDEX)
+        .map(|&x| {
+            prev = x ^ crc_index(prev);
+            prev
+        })
+        .collect()
+}
+
+/// Encode system chunk indices the way `decode_indices` reads them.
+pub fn encode_indices(indices: &[u16]) -> Vec<u16> {
+    let mut prev = 0;
+    indices
+        .iter()
+        .map(|&i| {
+            let x = i ^ crc_index(prev);
+            prev = i;
+            x
+        })
+        .collect()
+}
+
+impl PageHeader {
+    pub fn checksum(&self) -> u8 {
+        crc8(&self.as_bytes()[..0x10])
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.magic == PAGE_MAGIC
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.first_chunk == 0
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageKind {
+    System,
+    Data,
+    Free,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Chunk {
+    pub index: u16,
+    #[serde(with = "serde_bytes")]
+    pub data: [u8; CHUNK_SIZE],
+    pub crc16: u16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Page {
+    pub index: usize,
+    pub header: PageHeader,
+    pub kind: PageKind,
+    pub chunks: Vec<Chunk>,
+}
+
+impl Chunk {
+    pub fn checksum(&self) -> u16 {
+        let mut d = self.data.to_vec();
+        d.extend_from_slice(&self.index.to_le_bytes());
+        crc16(&d)
+    }
+
+    /// The CRC covers the data and the index, so this also catches indices
+    /// that were not decoded correctly.
+    pub fn is_valid(&self) -> bool {
+        self.checksum() == self.crc16
+    }
+}
+
+fn read_chunk(data: &[u8], index: u16) -> Chunk {
+    let d: [u8; CHUNK_SIZE] = data[..CHUNK_SIZE].try_into().unwrap();
+    let c = &data[CHUNK_SIZE..RAW_CHUNK_SIZE];
+    Chunk {
+        index,
+        data: d,
+        crc16: u16::from_le_bytes([c[0], c[1]]),
+    }
+}
+
+impl Page {
+    pub fn new(data: &[u8], index: usize) -> Result<Self, String> {
+        if data.len() < PAGE_SIZE {
+            return Err(format!("MFS page {index} is truncated"));
+        }
+        let (header, _) = PageHeader::read_from_prefix(data).unwrap();
+        if !header.is_valid() {
+            return Ok(Self {
+                index,
+                header,
+                kind: PageKind::Free,
+                chunks: Vec::new(),
+            });
+        }
+
+        let mut chunks = Vec::<Chunk>::new();
+        let kind = if header.is_system() {
+            let slice = &data[PAGE_HEADER_SIZE..];
+            let (idx, _) =
+                Ref::<_, [u16]>::from_prefix_with_elems(slice, SYS_PAGE_CHUNKS + 1).unwrap();
+            let base = SYS_CHUNKS_OFFSET;
+            for (i, &ci) in decode_indices(&idx[..SYS_PAGE_CHUNKS]).iter().enumerate() {
+                let o = base + i * RAW_CHUNK_SIZE;
+                chunks.push(read_chunk(&data[o..], ci));
+            }
+            PageKind::System
+        } else {
+            let free = &data[PAGE_HEADER_SIZE..DATA_CHUNKS_OFFSET];
+            let base = DATA_CHUNKS_OFFSET;
+            for (i, &f) in free.iter().enumerate() {
+                // 0xff marks a chunk that has never been written
+                if f == 0xff {
+                    continue;
+                }
+                let o = base + i * RAW_CHUNK_SIZE;
+                let ci = header.first_chunk.wrapping_add(i as u16);
+                chunks.push(read_chunk(&data[o..], ci));
+            }
+            PageKind::Data
+        };
+
+        Ok(Self {
+            index,
+            header,
+            kind,
+            chunks,
+        })
+    }
+}
+
+impl Display for Page {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = self.index;
+        let k = self.kind;
+        if k == PageKind::Free {
+            return write!(f, "{i:3} {k:?}");
+        }
+        let usn = self.header.usn;
+        let ec = self.header.erase_count;
+        let fc = self.header.first_chunk;
+        let c = self.chunks.len();
+        write!(
+            f,
+            "{i:3} {k:?} USN {usn:08x}, erased {ec} times, first chunk {fc:04x}, {c} chunks"
+        )
+    }
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct VolumeHeader {
+    pub magic: u32,
+    pub version: u32,
+    pub capacity: u32,
+    pub files: u16,
+}
+
+pub const VOLUME_HEADER_SIZE: usize = core::mem::size_of::<VolumeHeader>();
+
+impl Display for VolumeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = self.version;
+        let c = self.capacity;
+        let n = self.files;
+        write!(f, "version {v}, capacity 0x{c:08x}, {n} file slots")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct File {
+    pub index: usize,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl File {
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = self.index;
+        let s = self.size();
+        write!(f, "{i:4} 0x{s:06x}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MFS {
+    pub offset: usize,
+    pub pages: Vec<Page>,
+    pub header: VolumeHeader,
+    pub fat: Vec<u16>,
+    pub files: Vec<File>,
+}
+
+impl Display for MFS {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let o = self.offset;
+        let p = self.pages.len();
+        let h = self.header;
+        let n = self.files.len();
+        write!(f, "MFS @ {o:08x}, {p} pages, {h}, {n} files")
+    }
+}
+
+// FAT entries up to CHUNK_SIZE terminate a chain and hold the number of
+// bytes used in the last chunk; 0 means the file does not exist.
+const FAT_FREE: u16 = 0x0000;
+const FAT_UNUSED: u16 = 0xffff;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Geometry {
+    pub pages: usize,
+    pub sys_pages: usize,
+    pub data_pages: usize,
+}
+
+impl Geometry {
+    pub fn new(size: usize) -> Result<Self, String> {
+        let pages = size / PAGE_SIZE;
+        if pages < SYS_PAGE_RATIO {
+            return Err(format!("MFS too small: {pages} pages"));
+        }
+        let sys_pages = pages / SYS_PAGE_RATIO;
+        let data_pages = pages - sys_pages - 1;
+        Ok(Self {
+            pages,
+            sys_pages,
+            data_pages,
+        })
+    }
+
+    pub fn data_chunks(&self) -> usize {
+        self.data_pages * DATA_PAGE_CHUNKS
+    }
+
+    /// Number of the first data chunk; the numbering continues where the
+    /// system area ends.
+    pub fn data_base(&self) -> usize {
+        self.sys_pages * SYS_PAGE_CHUNKS
+    }
+}
+
+pub(crate) fn read_pages(data: &[u8], geo: &Geometry) -> Result<Vec<Page>, String> {
+    let mut pages = Vec::<Page>::new();
+    for i in 0..geo.pages {
+        let o = i * PAGE_SIZE;
+        pages.push(Page::new(&data[o..o + PAGE_SIZE], i)?);
+    }
+    Ok(pages)
+}
+
+/// Assemble the system volume from the system page chunks. Chunks failing
+/// their CRC are skipped, so an older copy of a chunk may take their place.
+pub(crate) fn system_volume(pages: &[Page]) -> Result<Vec<u8>, String> {
+    // Later writes supersede earlier ones, so replay in USN order.
+    let mut sys_pages: Vec<&Page> = pages
+        .iter()
+        .filter(|p| p.kind == PageKind::System)
+        .collect();
+    sys_pages.sort_by_key(|p| p.header.usn);
+    let mut sys_chunks = BTreeMap::<u16, [u8; CHUNK_SIZE]>::new();
+    for p in sys_pages {
+        for c in p.chunks.iter().filter(|c| c.is_valid()) {
+            sys_chunks.insert(c.index, c.data);
+        }
+    }
+    let Some(&last) = sys_chunks.keys().last() else {
+        return Err("MFS has no valid system chunks".to_string());
+    };
+    if let Some(i) = (0..last).find(|i| !sys_chunks.contains_key(i)) {
+        return Err(format!("MFS system chunk {i:04x} is missing or corrupted"));
+    }
+    let mut volume = vec![0u8; (last as usize + 1) * CHUNK_SIZE];
+    for (i, d) in &sys_chunks {
+        let o = *i as usize * CHUNK_SIZE;
+        volume[o..o + CHUNK_SIZE].copy_from_slice(d);
+    }
+    Ok(volume)
+}
+
+pub(crate) fn read_fat(volume: &[u8], geo: &Geometry) -> Result<(VolumeHeader, Vec<u16>), String> {
+    let Ok((header, _)) = VolumeHeader::read_from_prefix(volume) else {
+        return Err("cannot parse MFS volume header".to_string());
+    };
+    let n = header.files as usize + geo.data_chunks();
+    let slice = &volume[VOLUME_HEADER_SIZE..];
+    let Ok((r, _)) = Ref::<_, [u16]>::from_prefix_with_elems(slice, n) else {
+        return Err("cannot parse MFS file allocation table".to_string());
+    };
+    Ok((header, r.to_vec()))
+}
+
+/// Follow the FAT chain of a file. Returns the data chunk numbers, relative
+/// to the start of the data area, and the number of bytes used in the last
+/// chunk, or `None` if the file does not exist.
+pub(crate) fn file_chain(
+    fat: &[u16],
+    n_files: usize,
+    file: usize,
+) -> Result<Option<(Vec<usize>, usize)>, String> {
+    let mut ind = fat[file];
+    if ind == FAT_FREE || ind == FAT_UNUSED {
+        return Ok(None);
+    }
+    let mut chunks = Vec::<usize>::new();
+    loop {
+        let k = ind as usize;
+        if k < n_files || k >= fat.len() {
+            return Err(format!("MFS file {file}: bad chunk reference {ind:04x}"));
+        }
+        let c = k - n_files;
+        if chunks.contains(&c) {
+            return Err(format!("MFS file {file}: loop at data chunk {c:04x}"));
+        }
+        chunks.push(c);
+        ind = fat[k];
+        if ind as usize <= CHUNK_SIZE {
+            return Ok(Some((chunks, ind as usize)));
+        }
+    }
+}
+
+/// Collect the data chunks by their number. Chunks failing their CRC are
+/// left out.
+pub(crate) fn data_chunks(pages: &[Page]) -> BTreeMap<u16, [u8; CHUNK_SIZE]> {
+    let mut chunks = BTreeMap::<u16, [u8; CHUNK_SIZE]>::new();
+    for p in pages.iter().filter(|p| p.kind == PageKind::Data) {
+        for c in p.chunks.iter().filter(|c| c.is_valid()) {
+            chunks.insert(c.index, c.data);
+        }
+    }
+    chunks
+}
+
+/// Read all files by following their FAT chains through the data pages.
+pub(crate) fn read_files(
+    pages: &[Page],
+    geo: &Geometry,
+    header: &VolumeHeader,
+    fat: &[u16],
+) -> Result<Vec<File>, String> {
+    let n_files = header.files as usize;
+    let base = geo.data_base();
+    let chunks = data_chunks(pages);
+    let mut files = Vec::<File>::new();
+    for i in 0..n_files {
+        let Some((chain, last)) = file_chain(fat, n_files, i)? else {
+            continue;
+        };
+        let mut d = Vec::<u8>::new();
+        for (n, c) in chain.iter().enumerate() {
+            let ci = (base + c) as u16;
+            let Some(c) = chunks.get(&ci) else {
+                return Err(format!(
+                    "MFS file {i}: data chunk {ci:04x} is missing or corrupted"
+                ));
+            };
+            let s = if n == chain.len() - 1 {
+                last
+            } else {
+                CHUNK_SIZE
+            };
+            d.extend_from_slice(&c[..s]);
+        }
+        files.push(File { index: i, data: d });
+    }
+    Ok(files)
+}
+
+impl MFS {
+    pub fn new(data: &[u8], offset: usize) -> Result<Self, String> {
+        let mfs = Self::read(data, offset)?;
+        if mfs.header.magic != VOLUME_MAGIC {
+            let m = mfs.header.magic;
+            return Err(format!("MFS volume magic not found, got: {m:08x}"));
+        }
+        Ok(mfs)
+    }
+
+    /// Read any volume laid out like MFS, regardless of its volume magic.
+    pub(crate) fn read(data: &[u8], offset: usize) -> Result<Self, String> {
+        let geo = Geometry::new(data.len())?;
+        let pages = read_pages(data, &geo)?;
+        if pages.iter().all(|p| p.kind == PageKind::Free) {
+            return Err("no MFS pages found".to_string());
+        }
+        let volume = system_volume(&pages)?;
+        let (header, fat) = read_fat(&volume, &geo)?;
+        let files = read_files(&pages, &geo, &header, &fat)?;
+
+        Ok(Self {
+            offset,
+            pages,
+            header,
+            fat,
+            files,
+        })
+    }
+
+    pub fn file(&self, index: usize) -> Option<&File> {
+        self.files.iter().find(|f| f.index == index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // check values from the CRC catalogue, CRC-8/SMBUS and CRC-16/IBM-3740
+    #[test]
+    fn crc_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+}
@@ -0,0 +1,122 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::str::from_utf8;
+use zerocopy::{FromBytes, Ref};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
+
+use crate::mfs::tree::Mode;
+use crate::mfs::MFS;
+
+// intel.cfg and fitc.cfg carry the default contents of /home, i.e., the
+// settings provisioned by Intel and by the OEM via FITC respectively.
+// see https://github.com/ptresearch/parseMFS
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct Record {
+    pub name: [u8; 12],
+    pub _0c: u16,
+    pub mode: u16,
+    pub opt: u16,
+    pub size: u16,
+    pub uid: u16,
+    pub gid: u16,
+    pub offset: u32, // from the start of the config file
+}
+
+const PARENT: &str = "..";
+
+impl Record {
+    pub fn name(&self) -> String {
+        match from_utf8(&self.name) {
+            Ok(n) => n.trim_end_matches('\0').to_string(),
+            Err(_) => format!("{:02x?}", self.name),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    pub path: String,
+    pub record: Record,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl Entry {
+    pub fn mode(&self) -> Mode {
+        Mode(self.record.mode)
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = self.record;
+        let m = self.mode();
+        let opt = r.opt;
+        let u = r.uid;
+        let g = r.gid;
+        let o = r.offset;
+        let s = r.size;
+        let p = &self.path;
+        write!(f, "{m} {opt:04x} {u:5} {g:5} @ 0x{o:06x} (0x{s:04x}) {p}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Config {
+    pub entries: Vec<Entry>,
+}
+
+impl Config {
+    pub fn new(data: &[u8]) -> Result<Self, String> {
+        let Ok((count, rest)) = u32::read_from_prefix(data) else {
+            return Err("cannot read config record count".to_string());
+        };
+        let Ok((r, _)) = Ref::<_, [Record]>::from_prefix_with_elems(rest, count as usize) else {
+            return Err(format!("cannot parse {count} config records"));
+        };
+
+        // Records are listed depth first. A directory record is followed by
+        // its contents, which end with a ".." record.
+        let mut dirs = Vec::<String>::new();
+        let mut entries = Vec::<Entry>::new();
+        for record in r.iter() {
+            let name = record.name();
+            if name == PARENT {
+                dirs.pop();
+                continue;
+            }
+            let path = dirs
+                .iter()
+                .chain([&name])
+                .fold(String::new(), |p, n| format!("{p}/{n}"));
+            if Mode(record.mode).is_dir() {
+                dirs.push(name);
+            }
+            let o = record.offset as usize;
+            let s = record.size as usize;
+            let Some(d) = data.get(o..o + s) else {
+                return Err(format!("config data for {path} out of bounds"));
+            };
+            entries.push(Entry {
+                path,
+                record: *record,
+                data: d.to_vec(),
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Parse the config file with the given MFS file index, i.e.,
+    /// `tree::INTEL_CFG` or `tree::FITC_CFG`.
+    pub fn from_mfs(mfs: &MFS, index: usize) -> Result<Self, String> {
+        match mfs.file(index) {
+            Some(f) => Self::new(&f.data),
+            None => Err(format!("no config file {index} in MFS")),
+        }
+    }
+
+    pub fn find(&self, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.path == path)
+    }
+}
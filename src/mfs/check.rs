@@ -0,0 +1,288 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::mfs::{
+    data_chunks, file_chain, read_fat, read_pages, system_volume, Geometry, Page, PageKind,
+    CHUNK_SIZE, DATA_CHUNKS_OFFSET, DATA_PAGE_CHUNKS, PAGE_HEADER_SIZE, PAGE_SIZE, RAW_CHUNK_SIZE,
+    SYS_CHUNKS_OFFSET, SYS_PAGE_CHUNKS, VOLUME_MAGIC,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageReport {
+    pub index: usize,
+    pub kind: PageKind,
+    pub issues: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileReport {
+    pub index: usize,
+    pub size: usize,
+    pub issues: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Report {
+    pub offset: usize,
+    pub pages: Vec<PageReport>,
+    pub volume: Vec<String>,
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.volume.is_empty()
+            && self.pages.iter().all(|p| p.issues.is_empty())
+            && self.files.iter().all(|f| f.issues.is_empty())
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let o = self.offset;
+        let p = self.pages.len();
+        let bp = self.pages.iter().filter(|p| !p.issues.is_empty()).count();
+        let n = self.files.len();
+        let bf = self.files.iter().filter(|f| !f.issues.is_empty()).count();
+        write!(
+            f,
+            "MFS @ {o:08x}: {bp} of {p} pages and {bf} of {n} files inconsistent"
+        )?;
+        for i in &self.volume {
+            write!(f, "\n  volume: {i}")?;
+        }
+        for r in &self.pages {
+            for i in &r.issues {
+                write!(f, "\n  page {:3} ({:?}): {i}", r.index, r.kind)?;
+            }
+        }
+        for r in &self.files {
+            for i in &r.issues {
+                write!(f, "\n  file {:4} (0x{:06x}): {i}", r.index, r.size)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_erased(data: &[u8]) -> bool {
+    data.iter().all(|b| *b == 0xff)
+}
+
+fn check_page(raw: &[u8], page: &Page, bad_chunks: &mut BTreeSet<u16>) -> Vec<String> {
+    let mut issues = Vec::<String>::new();
+    let h = page.header;
+    if page.kind == PageKind::Free {
+        if !is_erased(raw) {
+            issues.push("neither a valid nor an erased page".to_string());
+        }
+        return issues;
+    }
+    let crc = h.checksum();
+    if crc != h.crc8 {
+        let c = h.crc8;
+        issues.push(format!("header CRC-8 {c:02x}, expected {crc:02x}"));
+    }
+    for c in &page.chunks {
+        let crc = c.checksum();
+        if crc != c.crc16 {
+            let i = c.index;
+            let s = c.crc16;
+            issues.push(format!("chunk {i:04x} CRC-16 {s:04x}, expected {crc:04x}"));
+            bad_chunks.insert(i);
+        }
+    }
+
+    // Whatever has not been written yet must still be erased.
+    let used = page.chunks.len();
+    let (total, flags, base) = match page.kind {
+        PageKind::System => {
+            let idx = &raw[PAGE_HEADER_SIZE + used * 2..SYS_CHUNKS_OFFSET];
+            if idx.len() > 2 && !is_erased(&idx[2..]) {
+                issues.push("chunk indices after the last chunk".to_string());
+            }
+            (SYS_PAGE_CHUNKS, None, SYS_CHUNKS_OFFSET)
+        }
+        _ => {
+            let flags = &raw[PAGE_HEADER_SIZE..DATA_CHUNKS_OFFSET];
+            if let Some(f) = flags.iter().find(|f| **f != 0 && **f != 0xff) {
+                issues.push(format!("invalid chunk free flag {f:02x}"));
+            }
+            (DATA_PAGE_CHUNKS, Some(flags), DATA_CHUNKS_OFFSET)
+        }
+    };
+    for i in 0..total {
+        let free = match flags {
+            Some(f) => f[i] == 0xff,
+            None => i >= used,
+        };
+        let o = base + i * RAW_CHUNK_SIZE;
+        if free && !is_erased(&raw[o..o + RAW_CHUNK_SIZE]) {
+            issues.push(format!("free chunk slot {i} is not erased"));
+        }
+    }
+    if let Some(e) = raw.get(base + total * RAW_CHUNK_SIZE..) {
+        if !is_erased(e) {
+            issues.push("page tail is not erased".to_string());
+        }
+    }
+    issues
+}
+
+/// Verify page headers, chunk CRCs, the erased state of unused space and
+/// the file allocation table of an MFS partition. Unlike `MFS::new`, this
+/// reports as much as possible instead of stopping at the first error.
+pub fn check(data: &[u8], offset: usize) -> Report {
+    let mut report = Report {
+        offset,
+        pages: Vec::new(),
+        volume: Vec::new(),
+        files: Vec::new(),
+    };
+    let geo = match Geometry::new(data.len()) {
+        Ok(g) => g,
+        Err(e) => {
+            report.volume.push(e);
+            return report;
+        }
+    };
+    let pages = match read_pages(data, &geo) {
+        Ok(p) => p,
+        Err(e) => {
+            report.volume.push(e);
+            return report;
+        }
+    };
+
+    let mut bad_chunks = BTreeSet::<u16>::new();
+    for p in &pages {
+        let o = p.index * PAGE_SIZE;
+        let issues = check_page(&data[o..o + PAGE_SIZE], p, &mut bad_chunks);
+        report.pages.push(PageReport {
+            index: p.index,
+            kind: p.kind,
+            issues,
+        });
+    }
+
+    let count = |k: PageKind| pages.iter().filter(|p| p.kind == k).count();
+    let (sys, dat, free) = (
+        count(PageKind::System),
+        count(PageKind::Data),
+        count(PageKind::Free),
+    );
+    if sys != geo.sys_pages || dat != geo.data_pages || free != 1 {
+        report.volume.push(format!(
+            "{sys} system, {dat} data and {free} free pages, expected {}, {} and 1",
+            geo.sys_pages, geo.data_pages
+        ));
+    }
+    let mut first_chunks = BTreeMap::<u16, usize>::new();
+    for p in pages.iter().filter(|p| p.kind == PageKind::Data) {
+        let fc = p.header.first_chunk;
+        if let Some(o) = first_chunks.insert(fc, p.index) {
+            let i = p.index;
+            report
+                .volume
+                .push(format!("pages {o} and {i} share first chunk {fc:04x}"));
+        }
+    }
+
+    let volume = match system_volume(&pages) {
+        Ok(v) => v,
+        Err(e) => {
+            report.volume.push(e);
+            return report;
+        }
+    };
+    let (header, fat) = match read_fat(&volume, &geo) {
+        Ok(r) => r,
+        Err(e) => {
+            report.volume.push(e);
+            return report;
+        }
+    };
+
+    if header.magic != VOLUME_MAGIC {
+        let m = header.magic;
+        report
+            .volume
+            .push(format!("volume magic {m:08x}, expected {VOLUME_MAGIC:08x}"));
+    }
+
+    let n_files = header.files as usize;
+    let base = geo.data_base();
+    let chunks = data_chunks(&pages);
+    let mut owners = BTreeMap::<usize, usize>::new();
+    for i in 0..n_files {
+        let mut issues = Vec::<String>::new();
+        let (chain, last) = match file_chain(&fat, n_files, i) {
+            Ok(Some(c)) => c,
+            Ok(None) => continue,
+            Err(e) => {
+                report.files.push(FileReport {
+                    index: i,
+                    size: 0,
+                    issues: vec![e],
+                });
+                continue;
+            }
+        };
+        for c in &chain {
+            let ci = (base + c) as u16;
+            if bad_chunks.contains(&ci) {
+                issues.push(format!("data chunk {ci:04x} is corrupted"));
+            } else if !chunks.contains_key(&ci) {
+                issues.push(format!("data chunk {ci:04x} is missing"));
+            }
+            if let Some(o) = owners.insert(*c, i) {
+                issues.push(format!("data chunk {ci:04x} is shared with file {o}"));
+            }
+        }
+        if last == 0 {
+            issues.push("last chunk is empty".to_string());
+        }
+        let size = (chain.len() - 1) * CHUNK_SIZE + last;
+        report.files.push(FileReport {
+            index: i,
+            size,
+            issues,
+        });
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mfs::{VolumeHeader, MFS, VOLUME_VERSION};
+
+    #[test]
+    fn no_data_pages() {
+        let header = VolumeHeader {
+            magic: VOLUME_MAGIC,
+            version: VOLUME_VERSION,
+            capacity: 0,
+            files: 256,
+        };
+        let mut m = MFS {
+            offset: 0,
+            pages: Vec::new(),
+            header,
+            fat: Vec::new(),
+            files: Vec::new(),
+        };
+        m.set_file(6, vec![0x11; 100]).unwrap();
+        let mut d = m.to_bytes(13 * PAGE_SIZE).unwrap();
+        d[PAGE_SIZE..].fill(0xff);
+
+        let r = check(&d, 0);
+        let f = r.files.iter().find(|f| f.index == 6).unwrap();
+        assert_eq!(f.size, 100);
+        assert_eq!(
+            f.issues,
+            ["data chunk 0078 is missing", "data chunk 0079 is missing"]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+use std::str::from_utf8;
+use zerocopy::{FromBytes, Ref};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes};
+
+use crate::mfs::MFS;
+
+// CSME 11+ keeps a directory hierarchy on top of the plain MFS files.
+// see https://github.com/ptresearch/parseMFS
+pub const INTEL_CFG: usize = 6;
+pub const FITC_CFG: usize = 7;
+pub const HOME_DIR: usize = 8;
+
+const FILENO_MASK: u32 = 0x0fff;
+// HMAC, anti-replay data and nonce appended to integrity protected files
+pub const SECURITY_BLOB_SIZE: usize = 0x34;
+// guards against directory loops in corrupted file systems
+const MAX_DEPTH: usize = 32;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mode(pub u16);
+
+impl Mode {
+    const PERMS: u16 = 0o777;
+    const INTEGRITY: u16 = 1 << 9;
+    const ENCRYPTION: u16 = 1 << 10;
+    const ANTI_REPLAY: u16 = 1 << 11;
+    const DIRECTORY: u16 = 1 << 12;
+
+    pub fn perms(&self) -> u16 {
+        self.0 & Self::PERMS
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.0 & Self::DIRECTORY != 0
+    }
+
+    pub fn integrity(&self) -> bool {
+        self.0 & Self::INTEGRITY != 0
+    }
+
+    pub fn encryption(&self) -> bool {
+        self.0 & Self::ENCRYPTION != 0
+    }
+
+    pub fn anti_replay(&self) -> bool {
+        self.0 & Self::ANTI_REPLAY != 0
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = if self.is_dir() { 'd' } else { '-' };
+        let p = self.perms();
+        let rwx: String = (0..9)
+            .map(|i| {
+                let set = p & (1 << (8 - i)) != 0;
+                match (set, i % 3) {
+                    (false, _) => '-',
+                    (true, 0) => 'r',
+                    (true, 1) => 'w',
+                    (true, _) => 'x',
+                }
+            })
+            .collect();
+        let i = if self.integrity() { 'I' } else { '-' };
+        let e = if self.encryption() { 'E' } else { '-' };
+        let a = if self.anti_replay() { 'A' } else { '-' };
+        write!(f, "{d}{rwx} {i}{e}{a}")
+    }
+}
+
+#[derive(Immutable, IntoBytes, FromBytes, Serialize, Deserialize, Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct DirEntry {
+    pub fileno: u32, // low 12 bits are the MFS file index
+    pub mode: u16,
+    pub uid: u16,
+    pub gid: u16,
+    pub salt: u16,
+    pub name: [u8; 12],
+}
+
+impl DirEntry {
+    pub fn name(&self) -> String {
+        match from_utf8(&self.name) {
+            Ok(n) => n.trim_end_matches('\0').to_string(),
+            Err(_) => format!("{:02x?}", self.name),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        (self.fileno & FILENO_MASK) as usize
+    }
+}
+
+const DIR_ENTRY_SIZE: usize = core::mem::size_of::<DirEntry>();
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub index: usize,
+    pub uid: u16,
+    pub gid: u16,
+    pub mode: Mode,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub security: Vec<u8>,
+    pub children: Vec<Node>,
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.mode;
+        let u = self.uid;
+        let g = self.gid;
+        let s = self.data.len();
+        let n = &self.name;
+        let i = self.index;
+        write!(f, "{m} {u:5} {g:5} {s:8} {i:4} {n}")
+    }
+}
+
+impl Node {
+    fn new(mfs: &MFS, name: &str, index: usize, mode: Mode, uid: u16, gid: u16) -> Self {
+        let mut data = match mfs.file(index) {
+            Some(f) => f.data.clone(),
+            None => Vec::new(),
+        };
+        let security = if mode.integrity() && data.len() >= SECURITY_BLOB_SIZE {
+            data.split_off(data.len() - SECURITY_BLOB_SIZE)
+        } else {
+            Vec::new()
+        };
+        Self {
+            name: name.to_string(),
+            index,
+            uid,
+            gid,
+            mode,
+            data,
+            security,
+            children: Vec::new(),
+        }
+    }
+
+    fn read_dir(&mut self, mfs: &MFS, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("MFS directory nesting too deep at {}", self.name));
+        }
+        let count = self.data.len() / DIR_ENTRY_SIZE;
+        let Ok((r, _)) = Ref::<_, [DirEntry]>::from_prefix_with_elems(&self.data[..], count) else {
+            return Err(format!("cannot parse MFS directory {}", self.name));
+        };
+        for e in r.iter() {
+            let name = e.name();
+            if name == "." || name == ".." {
+                continue;
+            }
+            let mode = Mode(e.mode);
+            let mut n = Self::new(mfs, &name, e.index(), mode, e.uid, e.gid);
+            if mode.is_dir() {
+                n.read_dir(mfs, depth + 1)?;
+            }
+            self.children.push(n);
+        }
+        Ok(())
+    }
+
+    /// Rebuild the CSME 11+ directory tree from the MFS files.
+    /// The root holds the configuration files and the `home` directory.
+    pub fn root(mfs: &MFS) -> Result<Self, String> {
+        let Some(h) = mfs.file(HOME_DIR) else {
+            return Err("no home directory in MFS".to_string());
+        };
+        let mut root = Self {
+            name: String::new(),
+            index: 0,
+            uid: 0,
+            gid: 0,
+            mode: Mode(Mode::DIRECTORY | 0o755),
+            data: Vec::new(),
+            security: Vec::new(),
+            children: Vec::new(),
+        };
+        for (name, index) in [("intel.cfg", INTEL_CFG), ("fitc.cfg", FITC_CFG)] {
+            if mfs.file(index).is_some() {
+                let n = Self::new(mfs, name, index, Mode(0o444), 0, 0);
+                root.children.push(n);
+            }
+        }
+        // The "." entry of a directory describes the directory itself.
+        let (mode, uid, gid) = match DirEntry::read_from_prefix(&h.data) {
+            Ok((e, _)) if e.name() == "." => (Mode(e.mode), e.uid, e.gid),
+            _ => (Mode(Mode::DIRECTORY | 0o755), 0, 0),
+        };
+        let mut home = Self::new(mfs, "home", HOME_DIR, mode, uid, gid);
+        home.read_dir(mfs, 1)?;
+        root.children.push(home);
+        Ok(root)
+    }
+
+    /// Look up a node by its absolute or relative path, e.g. `/home/mca`.
+    pub fn find(&self, path: &str) -> Option<&Self> {
+        let mut node = self;
+        for p in path.split('/').filter(|p| !p.is_empty() && *p != ".") {
+            node = node.children.iter().find(|c| c.name == p)?;
+        }
+        Some(node)
+    }
+}
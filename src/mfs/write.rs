@@ -0,0 +1,274 @@
+use zerocopy::IntoBytes;
+
+use crate::fpt::FPTEntry;
+use crate::mfs::cfg::Config;
+use crate::mfs::tree::{Node, FITC_CFG, INTEL_CFG};
+use crate::mfs::{
+    encode_indices, Chunk, File, Geometry, Page, PageHeader, PageKind, CHUNK_SIZE,
+    DATA_CHUNKS_OFFSET, DATA_PAGE_CHUNKS, MFS, PAGE_HEADER_SIZE, PAGE_MAGIC, PAGE_SIZE,
+    RAW_CHUNK_SIZE, SYS_CHUNKS_OFFSET, SYS_PAGE_CHUNKS,
+};
+
+// Erase counts and the next page to erase are wear leveling state, so
+// they are taken over from the page previously at the same position.
+fn page_header(usn: u32, first_chunk: u16, source: Option<&Page>) -> PageHeader {
+    let (erase_count, next_erase) = match source {
+        Some(p) if p.kind != PageKind::Free => (p.header.erase_count, p.header.next_erase),
+        _ => (0, 0),
+    };
+    let mut h = PageHeader {
+        magic: PAGE_MAGIC,
+        usn,
+        erase_count,
+        next_erase,
+        first_chunk,
+        crc8: 0,
+        _11: 0,
+    };
+    h.crc8 = h.checksum();
+    h
+}
+
+fn write_chunk(page: &mut [u8], o: usize, index: u16, data: &[u8]) {
+    let mut d = [0u8; CHUNK_SIZE];
+    d[..data.len()].copy_from_slice(data);
+    let c = Chunk {
+        index,
+        data: d,
+        crc16: 0,
+    };
+    page[o..o + CHUNK_SIZE].copy_from_slice(&d);
+    page[o + CHUNK_SIZE..o + RAW_CHUNK_SIZE].copy_from_slice(&c.checksum().to_le_bytes());
+}
+
+impl MFS {
+    /// Add a file or replace its contents.
+    pub fn set_file(&mut self, index: usize, data: Vec<u8>) -> Result<(), String> {
+        if index >= self.header.files as usize {
+            let n = self.header.files;
+            return Err(format!(
+                "file index {index} out of range, MFS has {n} slots"
+            ));
+        }
+        // The FAT has no way to express a file without data.
+        if data.is_empty() {
+            return Err(format!("cannot store empty file {index}"));
+        }
+        match self.files.iter_mut().find(|f| f.index == index) {
+            Some(f) => f.data = data,
+            None => {
+                self.files.push(File { index, data });
+                self.files.sort_by_key(|f| f.index);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, index: usize) -> Result<(), String> {
+        let Some(p) = self.files.iter().position(|f| f.index == index) else {
+            return Err(format!("no file {index} in MFS"));
+        };
+        self.files.remove(p);
+        Ok(())
+    }
+
+    /// Drop the runtime state and keep only the static configuration,
+    /// i.e., intel.cfg and fitc.cfg, from which the ME recreates /home.
+    /// Only CSME 11+ keeps those in files 6 and 7, so other volumes are
+    /// refused.
+    pub fn reset(&mut self) -> Result<(), String> {
+        if let Err(e) = Node::root(self).and(Config::from_mfs(self, INTEL_CFG)) {
+            return Err(format!("not a CSME 11+ MFS, refusing to reset: {e}"));
+        }
+        self.files
+            .retain(|f| f.index == INTEL_CFG || f.index == FITC_CFG);
+        Ok(())
+    }
+
+    /// Lay out the files anew in a partition of the given size.
+    /// All pages get fresh headers, chunk CRCs and a new FAT.
+    pub fn to_bytes(&self, size: usize) -> Result<Vec<u8>, String> {
+        let geo = Geometry::new(size)?;
+        let n_files = self.header.files as usize;
+
+        let base = geo.data_base();
+
+        // FAT values up to CHUNK_SIZE end a chain, so with few file slots
+        // the first data chunks cannot be referenced and stay unused.
+        let skip = (CHUNK_SIZE + 1).saturating_sub(n_files);
+        let mut fat = vec![0u16; n_files + geo.data_chunks()];
+        let mut chunks = Vec::<&[u8]>::new();
+        for f in &self.files {
+            if f.data.is_empty() {
+                return Err(format!("cannot store empty file {}", f.index));
+            }
+            for (n, d) in f.data.chunks(CHUNK_SIZE).enumerate() {
+                let k = skip + chunks.len();
+                if k >= geo.data_chunks() {
+                    return Err(format!("files do not fit into 0x{size:08x} bytes"));
+                }
+                let r = (n_files + k) as u16;
+                if n == 0 {
+                    fat[f.index] = r;
+                } else {
+                    fat[n_files + k - 1] = r;
+                }
+                chunks.push(d);
+            }
+            let last = n_files + skip + chunks.len() - 1;
+            fat[last] = chunks[chunks.len() - 1].len() as u16;
+        }
+
+        let mut volume = self.header.as_bytes().to_vec();
+        volume.extend_from_slice(fat.as_bytes());
+        let sys_chunks: Vec<&[u8]> = volume.chunks(CHUNK_SIZE).collect();
+        if sys_chunks.len() > geo.sys_pages * SYS_PAGE_CHUNKS {
+            return Err("system volume does not fit into the system pages".to_string());
+        }
+
+        let mut out = vec![0xffu8; geo.pages * PAGE_SIZE];
+        let mut usn = 1;
+        for (p, page_chunks) in sys_chunks.chunks(SYS_PAGE_CHUNKS).enumerate() {
+            let page = &mut out[p * PAGE_SIZE..(p + 1) * PAGE_SIZE];
+            let h = page_header(usn, 0, self.pages.get(p));
+            page[..PAGE_HEADER_SIZE].copy_from_slice(h.as_bytes());
+            usn += 1;
+            let first = p * SYS_PAGE_CHUNKS;
+            let indices: Vec<u16> = (first..first + page_chunks.len())
+                .map(|i| i as u16)
+                .collect();
+            let stored = encode_indices(&indices);
+            page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + stored.len() * 2]
+                .copy_from_slice(stored.as_bytes());
+            for (i, d) in page_chunks.iter().enumerate() {
+                write_chunk(page, SYS_CHUNKS_OFFSET + i * RAW_CHUNK_SIZE, indices[i], d);
+            }
+        }
+        // Remaining system pages are written without chunks so that the
+        // page count matches the geometry.
+        for p in sys_chunks.len().div_ceil(SYS_PAGE_CHUNKS)..geo.sys_pages {
+            let page = &mut out[p * PAGE_SIZE..(p + 1) * PAGE_SIZE];
+            let h = page_header(usn, 0, self.pages.get(p));
+            page[..PAGE_HEADER_SIZE].copy_from_slice(h.as_bytes());
+            usn += 1;
+        }
+
+        for p in 0..geo.data_pages {
+            let first = base + p * DATA_PAGE_CHUNKS;
+            let n = geo.sys_pages + p;
+            let o = n * PAGE_SIZE;
+            let page = &mut out[o..o + PAGE_SIZE];
+            let h = page_header(usn, first as u16, self.pages.get(n));
+            page[..PAGE_HEADER_SIZE].copy_from_slice(h.as_bytes());
+            usn += 1;
+            for i in 0..DATA_PAGE_CHUNKS {
+                let Some(k) = (p * DATA_PAGE_CHUNKS + i).checked_sub(skip) else {
+                    continue;
+                };
+                let Some(d) = chunks.get(k) else {
+                    break;
+                };
+                page[PAGE_HEADER_SIZE + i] = 0;
+                let ci = (first + i) as u16;
+                write_chunk(page, DATA_CHUNKS_OFFSET + i * RAW_CHUNK_SIZE, ci, d);
+            }
+        }
+        // The last page stays erased as the spare page.
+
+        // NOTE: The partition may be larger than the pages it holds.
+        out.resize(size, 0xff);
+        Ok(out)
+    }
+
+    /// Serialize the MFS into the image at its original offset, sized to
+    /// fit the given FPT entry.
+    pub fn write_into(&self, image: &mut [u8], entry: &FPTEntry) -> Result<(), String> {
+        let s = entry.size as usize;
+        let o = self.offset;
+        let Some(dest) = image.get_mut(o..o + s) else {
+            return Err(format!("MFS @ {o:08x} (0x{s:08x}) exceeds the image"));
+        };
+        let d = self.to_bytes(s)?;
+        dest.copy_from_slice(&d);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mfs::{check, VolumeHeader, VOLUME_MAGIC, VOLUME_VERSION};
+
+    const SIZE: usize = 13 * PAGE_SIZE;
+
+    fn volume(files: u16) -> MFS {
+        let header = VolumeHeader {
+            magic: VOLUME_MAGIC,
+            version: VOLUME_VERSION,
+            capacity: 0,
+            files,
+        };
+        MFS {
+            offset: 0,
+            pages: Vec::new(),
+            header,
+            fat: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    fn write_and_parse(mfs: &MFS) -> MFS {
+        let d = mfs.to_bytes(SIZE).unwrap();
+        let r = check::check(&d, 0);
+        assert!(r.is_ok(), "{r}");
+        MFS::new(&d, 0).unwrap()
+    }
+
+    fn contents(mfs: &MFS) -> Vec<(usize, Vec<u8>)> {
+        mfs.files
+            .iter()
+            .map(|f| (f.index, f.data.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        // few file slots make FAT pointers collide with chain terminators
+        for slots in [16, 64, 65, 256] {
+            let mut m = volume(slots);
+            m.set_file(3, vec![0x11; 100]).unwrap();
+            m.set_file(9, b"hello".to_vec()).unwrap();
+            let mut parsed = write_and_parse(&m);
+            assert_eq!(contents(&parsed), contents(&m));
+
+            parsed.set_file(3, vec![0x22; 300]).unwrap();
+            parsed.remove_file(9).unwrap();
+            parsed.set_file(5, vec![0x33; CHUNK_SIZE]).unwrap();
+            parsed.pages[1].header.erase_count = 7;
+            parsed.pages[1].header.next_erase = 3;
+            let again = write_and_parse(&parsed);
+            assert_eq!(contents(&again), contents(&parsed));
+            let h = again.pages[1].header;
+            assert_eq!((h.erase_count, h.next_erase), (7, 3));
+        }
+    }
+
+    #[test]
+    fn erased_data_pages() {
+        let mut m = volume(256);
+        m.set_file(6, vec![0x11; 100]).unwrap();
+        let mut d = m.to_bytes(SIZE).unwrap();
+        d[PAGE_SIZE..].fill(0xff);
+        let e = MFS::new(&d, 0).unwrap_err();
+        assert_eq!(e, "MFS file 6: data chunk 0078 is missing or corrupted");
+    }
+
+    #[test]
+    fn reset_needs_csme11_layout() {
+        let mut m = volume(256);
+        m.set_file(6, vec![0x11; 100]).unwrap();
+        m.set_file(9, b"hello".to_vec()).unwrap();
+        assert!(m.reset().is_err());
+        assert_eq!(m.files.len(), 2);
+    }
+}